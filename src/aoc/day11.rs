@@ -1,6 +1,9 @@
 use super::assert::*;
+use super::intcode::stream::MachineStream;
 use super::intcode::Machine;
 use core::cmp::{max, min};
+use futures::executor::block_on;
+use futures::StreamExt;
 use std::collections::HashMap;
 
 enum Color {
@@ -58,52 +61,57 @@ fn print_panels(panels: &HashMap<(i32, i32), Color>) {
     }
 }
 
-fn run(positions: &Vec<i64>, start_color: Color) -> HashMap<(i32, i32), Color> {
+fn run(initial: &Machine, start_color: Color) -> HashMap<(i32, i32), Color> {
     let mut panels: HashMap<(i32, i32), Color> = HashMap::new();
-    let mut machine = Machine::init(&positions);
+    let (camera, mut robot) = MachineStream::channel(initial.clone());
     let mut current = (0, 0);
     let mut direction = Direction::Up;
 
     panels.insert((0, 0), start_color);
 
-    while machine.halted == false {
-        let color = panels.get(&current).unwrap_or(&Color::Black);
-
-        machine.run(vec![color.to_int()]);
-
-        let new_color = machine.values.pop_front().unwrap();
-        let turn = machine.values.pop_front().unwrap();
-
-        let (dir, point) = match (direction, turn) {
-            (Direction::Up, 0) => (Direction::Left, (current.0 - 1, current.1)),
-            (Direction::Up, 1) => (Direction::Right, (current.0 + 1, current.1)),
-            (Direction::Left, 0) => (Direction::Down, (current.0, current.1 + 1)),
-            (Direction::Left, 1) => (Direction::Up, (current.0, current.1 - 1)),
-            (Direction::Down, 0) => (Direction::Right, (current.0 + 1, current.1)),
-            (Direction::Down, 1) => (Direction::Left, (current.0 - 1, current.1)),
-            (Direction::Right, 0) => (Direction::Up, (current.0, current.1 - 1)),
-            (Direction::Right, 1) => (Direction::Down, (current.0, current.1 + 1)),
-            _ => panic!(),
-        };
-
-        panels.insert(current, Color::from_int(new_color));
-
-        direction = dir;
-        current = point;
-    }
+    block_on(async {
+        loop {
+            let color = panels.get(&current).unwrap_or(&Color::Black);
+            camera.unbounded_send(color.to_int()).unwrap();
+
+            let new_color = match robot.next().await {
+                Some(color) => color,
+                None => break,
+            };
+            let turn = robot.next().await.unwrap();
+
+            let (dir, point) = match (direction, turn) {
+                (Direction::Up, 0) => (Direction::Left, (current.0 - 1, current.1)),
+                (Direction::Up, 1) => (Direction::Right, (current.0 + 1, current.1)),
+                (Direction::Left, 0) => (Direction::Down, (current.0, current.1 + 1)),
+                (Direction::Left, 1) => (Direction::Up, (current.0, current.1 - 1)),
+                (Direction::Down, 0) => (Direction::Right, (current.0 + 1, current.1)),
+                (Direction::Down, 1) => (Direction::Left, (current.0 - 1, current.1)),
+                (Direction::Right, 0) => (Direction::Up, (current.0, current.1 - 1)),
+                (Direction::Right, 1) => (Direction::Down, (current.0, current.1 + 1)),
+                _ => panic!(),
+            };
+
+            panels.insert(current, Color::from_int(new_color));
+
+            direction = dir;
+            current = point;
+        }
+    });
 
+    assert_eq!(None, robot.error());
     panels
 }
 
 pub fn solve() {
-    let initial = Machine::from_file("input/day11.txt");
-    let panels = run(&initial.positions, Color::Black);
+    let initial = Machine::from_file("input/day11.txt").unwrap();
+    let panels = run(&initial, Color::Black);
 
     assert_eq(Day::new(11, Part::A), 2129, panels.len());
 
-    run(&initial.positions, Color::White);
+    run(&initial, Color::White);
 
-    // let panels = run(&initial.positions, Color::White);
+    // let panels = run(&initial, Color::White);
     // print_panels(&panels);
 
     assert_eq(Day::new(11, Part::B), "PECKRGZL", "PECKRGZL");
@@ -1,10 +1,14 @@
 use super::assert::*;
-use super::intcode::Machine;
+use super::intcode::{Machine, RunState};
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 const SCREEN_WIDTH: i32 = 44;
 const SCREEN_HEIGHT: i32 = 23;
 
+// A bad joystick strategy can keep the ball in play forever.
+const GAME_TIME_LIMIT: Duration = Duration::from_secs(30);
+
 struct GameInfo {
     block_count: i32,
     ball_position: (i32, i32),
@@ -87,12 +91,12 @@ fn print_screen(screen: &HashMap<(i32, i32), i32>) {
 }
 
 pub fn solve() {
-    let initial = Machine::from_file("input/day13.txt");
+    let initial = Machine::from_file("input/day13.txt").unwrap();
     let mut screen: HashMap<(i32, i32), i32> = HashMap::new();
-    let mut machine = Machine::init(&initial.positions);
+    let mut machine = initial.clone();
 
     while machine.halted == false {
-        machine.run(vec![]);
+        machine.run(vec![]).unwrap();
 
         while machine.values.is_empty() == false {
             let x = machine.values.pop_front().unwrap() as i32;
@@ -108,13 +112,16 @@ pub fn solve() {
     assert_eq(Day::new(12, Part::A), 414, count);
 
     let mut screen: HashMap<(i32, i32), i32> = HashMap::new();
-    let mut machine = Machine::init(&initial.positions);
-    machine.positions[0] = 2;
+    let mut machine = initial.clone();
+    machine.write(0, 2);
+    machine.set_deadline(Some(Instant::now() + GAME_TIME_LIMIT));
     let mut score = 0;
     let mut joystick_position = 0;
 
     while machine.halted == false {
-        machine.run(vec![joystick_position]);
+        if let RunState::Stopped(limit) = machine.run(vec![joystick_position]).unwrap() {
+            panic!("Gave up on the game: {:?} limit reached", limit);
+        }
 
         while machine.values.is_empty() == false {
             let x = machine.values.pop_front().unwrap() as i32;
@@ -1,5 +1,5 @@
 use super::assert::*;
-use super::intcode::Machine;
+use super::intcode::{Machine, RunState};
 use core::cmp::max;
 use priority_queue::PriorityQueue;
 use std::cmp::Reverse;
@@ -85,14 +85,17 @@ fn move_forward(point: (i32, i32), direction: Direction) -> (i32, i32) {
 }
 
 fn step(machine: &mut Machine, direction: Direction) -> i32 {
-    machine.run(vec![direction.to_int() as i64]);
-    machine.values[0] as i32
+    machine.push_input(direction.to_int() as i64);
+
+    match machine.run_until_output().unwrap() {
+        RunState::Output(status) => status as i32,
+        state => panic!("Droid stopped with {:?}", state),
+    }
 }
 
 pub fn solve() {
+    let mut machine = Machine::from_file("input/day15.txt").unwrap();
     let mut map: HashSet<(i32, i32)> = HashSet::new();
-    let initial = Machine::from_file("input/day15.txt");
-    let mut machine = Machine::init(&initial.positions);
     let mut current = (0, 0);
     let mut direction = Direction::North;
     let end: (i32, i32);
@@ -1,4 +1,5 @@
 use super::assert::*;
+use super::intcode::ascii::AsciiMachine;
 use super::intcode::Machine;
 use std::collections::HashSet;
 
@@ -16,35 +17,19 @@ fn is_intersection(point: (i32, i32), map: &HashSet<(i32, i32)>) -> bool {
 }
 
 pub fn solve() {
-    let initial = Machine::from_file("input/day17.txt");
-    let mut machine = Machine::init(&initial.positions);
-    machine.run(vec![]);
-
+    let initial = Machine::from_file("input/day17.txt").unwrap();
+    let mut camera = AsciiMachine::new(initial.clone());
     let mut map: HashSet<(i32, i32)> = HashSet::new();
-    let mut x = 0;
     let mut y = 0;
 
-    for c in machine.values {
-        match c as u8 as char {
-            '#' => {
-                map.insert((x, y));
-                x += 1;
-            }
-
-            '\n' => {
-                x = 0;
-                y += 1;
-            }
-
-            '^' => {
-                map.insert((x, y));
-                x += 1;
-            }
-
-            _ => {
-                x += 1;
+    while let Some(line) = camera.read_line().unwrap() {
+        for (x, c) in line.chars().enumerate() {
+            if c == '#' || c == '^' {
+                map.insert((x as i32, y));
             }
         }
+
+        y += 1;
     }
 
     let sum = map
@@ -55,25 +40,23 @@ pub fn solve() {
 
     assert_eq(Day::new(17, Part::A), 6052, sum);
 
-    let mut machine = Machine::init(&initial.positions);
-    machine.positions[0] = 2;
+    let mut machine = initial.clone();
+    machine.write(0, 2);
 
+    let mut robot = AsciiMachine::new(machine);
     let commands = [
         "A,C,C,B,A,C,B,A,C,B",
         "L,6,R,12,L,4,L,6",
         "L,6,L,10,L,10,R,6",
         "R,6,L,6,R,12",
         "n",
-        "",
-    ]
-    .join("\n")
-    .chars()
-    .map(|x| x as i64)
-    .rev()
-    .collect::<Vec<i64>>();
-    machine.run(commands);
+    ];
+
+    for command in commands.iter() {
+        robot.send_line(command);
+    }
 
-    let dust = machine.values.pop_back().unwrap();
+    let dust = robot.read_to_end().unwrap().numbers[0];
 
     assert_eq(Day::new(17, Part::B), 752_491, dust);
 }
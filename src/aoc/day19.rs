@@ -1,24 +1,24 @@
 use super::assert::*;
+use super::intcode::batch::Batch;
 use super::intcode::Machine;
 
 pub fn in_beam(point: (i64, i64), machine: &Machine) -> bool {
-    let mut machine = Machine::init(&machine.positions);
-    machine.run(vec![point.1, point.0]);
+    let mut machine = machine.clone();
+    machine.run_to_halt(vec![point.0, point.1]).unwrap();
 
     machine.values.pop_front() == Some(1)
 }
 
 pub fn solve() {
-    let machine = Machine::from_file("input/day19.txt");
-    let mut count = 0;
-
-    for y in 0..50 {
-        for x in 0..50 {
-            if in_beam((x, y), &machine) {
-                count += 1;
-            }
-        }
-    }
+    let machine = Machine::from_file("input/day19.txt").unwrap();
+    let points: Vec<Vec<i64>> = (0..50)
+        .flat_map(|y| (0..50).map(move |x| vec![x, y]))
+        .collect();
+    let count = Batch::new(&machine)
+        .run(&points)
+        .into_iter()
+        .filter(|result| result.as_ref().unwrap() == &[1])
+        .count();
 
     assert_eq(Day::new(19, Part::A), 171, count);
 
@@ -1,38 +1,41 @@
 use super::assert::*;
+use super::intcode::ascii::AsciiMachine;
 use super::intcode::Machine;
 
+// Far more than either script needs, but a mistake can't hang the run.
+const INSTRUCTION_BUDGET: u64 = 100_000_000;
+
+fn hull_damage(initial: &Machine, script: &[&str]) -> i64 {
+    let mut droid = AsciiMachine::new(initial.clone());
+    droid
+        .machine
+        .set_instruction_budget(Some(INSTRUCTION_BUDGET));
+
+    for line in script {
+        droid.send_line(line);
+    }
+
+    droid.read_to_end().unwrap().numbers[0]
+}
+
 pub fn solve() {
-    let initial = Machine::from_file("input/day21.txt");
-    let mut machine = Machine::init(&initial.positions);
-    let commands = ["NOT A J", "NOT C T", "AND D T", "OR T J", "WALK", ""]
-        .join("\n")
-        .chars()
-        .map(|x| x as i64)
-        .rev()
-        .collect::<Vec<i64>>();
-
-    machine.run(commands);
+    let initial = Machine::from_file("input/day21.txt").unwrap();
+    let script = ["NOT A J", "NOT C T", "AND D T", "OR T J", "WALK"];
+
     assert_eq(
         Day::new(21, Part::A),
         19354890,
-        machine.values.pop_back().unwrap(),
+        hull_damage(&initial, &script),
     );
 
-    let mut machine = Machine::init(&initial.positions);
-    let commands = [
+    let script = [
         "NOT A J", "AND D J", "NOT B T", "AND D T", "AND H T", "OR T J", "NOT C T", "AND D T",
-        "AND E T", "OR T J", "NOT C T", "AND D T", "AND H T", "OR T J", "RUN", "",
-    ]
-    .join("\n")
-    .chars()
-    .map(|x| x as i64)
-    .rev()
-    .collect::<Vec<i64>>();
-
-    machine.run(commands);
+        "AND E T", "OR T J", "NOT C T", "AND D T", "AND H T", "OR T J", "RUN",
+    ];
+
     assert_eq(
         Day::new(21, Part::B),
         1140664209,
-        machine.values.pop_back().unwrap(),
+        hull_damage(&initial, &script),
     );
 }
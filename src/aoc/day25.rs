@@ -1,38 +1,34 @@
+use super::intcode::ascii::AsciiMachine;
+use super::intcode::diff::diff;
+use super::intcode::snapshot::Format;
 use super::intcode::Machine;
 use std::io::stdin;
 
-fn run_command(machine: &mut Machine, command: String) {
-    let mut input: Vec<i64> = Vec::new();
-
-    for c in command.chars().rev() {
-        input.push(c as i64);
-    }
-
-    machine.run(input);
-}
-
-fn reboot(machine: &Machine) -> Machine {
-    let mut machine = Machine::init(&machine.positions);
-    machine.run(vec![]);
-    machine
+fn run_command(droid: &mut AsciiMachine, command: &str) -> String {
+    droid.send_line(command);
+    droid.read_until_prompt().unwrap().0.text
 }
 
-fn rewind(commands: &mut Vec<String>, mut machine: &mut Machine) {
+// Steps the droid back to before the last command.
+fn rewind(droid: &mut AsciiMachine, history: &mut Vec<(usize, String)>) -> String {
     println!("Rewinding");
-    commands.pop();
+    let (count, text) = history.pop().unwrap();
 
-    for c in commands.iter() {
-        run_command(&mut machine, c.to_string());
-    }
+    droid.machine.rewind_to(count);
+    droid.machine.inputs.clear();
+    text
 }
 
 #[allow(dead_code)]
 pub fn solve() {
-    let initial = Machine::from_file("input/day25.txt");
     let mut commands: Vec<String> = Vec::new();
-    let mut machine = Machine::init(&initial.positions);
+    let mut droid = AsciiMachine::new(Machine::from_file("input/day25.txt").unwrap());
+    let mut text = droid.read_until_prompt().unwrap().0.text;
 
-    machine.run(vec![]);
+    // Instruction count and the text on screen before each command.
+    let mut shown = String::new();
+    let mut history: Vec<(usize, String)> = Vec::new();
+    droid.machine.start_journal();
 
     // Items in your inventory:
     // - hypercube
@@ -41,15 +37,17 @@ pub fn solve() {
     // - astronaut ice cream
 
     loop {
-        let bytes: Vec<u8> = machine.values.drain(..).map(|x| x as u8).collect();
-        let string = String::from_utf8(bytes).unwrap();
-        println!("{}", string);
+        println!("{}", text);
+
+        if !text.is_empty() {
+            shown = std::mem::take(&mut text);
+        }
 
         // Restart
-        if machine.halted {
+        if droid.halted() {
             println!("Game Over!");
-            machine = reboot(&initial);
-            rewind(&mut commands, &mut machine);
+            text = rewind(&mut droid, &mut history);
+            commands.pop();
 
             continue;
         }
@@ -57,9 +55,58 @@ pub fn solve() {
         let mut line = String::new();
 
         match stdin().read_line(&mut line) {
+            Ok(0) => break,
             Ok(_) => {
-                commands.push(line.clone());
-                run_command(&mut machine, line);
+                let words: Vec<&str> = line.split_whitespace().collect();
+
+                match words.as_slice() {
+                    ["save", path] => {
+                        let format = if path.ends_with(".bin") {
+                            Format::Binary
+                        } else {
+                            Format::Text
+                        };
+
+                        match droid.machine.save(path, format) {
+                            Ok(()) => println!("Saved to {}", path),
+                            Err(error) => println!("Error: {}", error),
+                        }
+                        continue;
+                    }
+                    // Memory changed by the last command, to find where items
+                    // and the current room are stored.
+                    ["diff"] => {
+                        let previous = history
+                            .last()
+                            .and_then(|(count, _)| droid.machine.branch(*count));
+
+                        match previous {
+                            Some(before) => print!("{}", diff(&before, &droid.machine)),
+                            None => println!("No command yet"),
+                        }
+                        continue;
+                    }
+                    ["load", path] => {
+                        match Machine::load(path) {
+                            Ok(loaded) => {
+                                println!("Loaded {}", path);
+                                droid = AsciiMachine::new(loaded);
+                                droid.machine.start_journal();
+                                commands.clear();
+                                history.clear();
+                            }
+                            Err(error) => println!("Error: {}", error),
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+
+                let command = line.trim_end().to_string();
+                let count = droid.machine.instruction_count().unwrap();
+                history.push((count, shown.clone()));
+                text = run_command(&mut droid, &command);
+                commands.push(command);
             }
 
             Err(error) => {
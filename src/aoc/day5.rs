@@ -2,17 +2,17 @@ use super::assert::*;
 use super::intcode::Machine;
 
 pub fn solve() {
-    let initial = Machine::from_file("input/day5.txt");
-    let mut machine = Machine::init(&initial.positions);
-    machine.run(vec![1]);
+    let initial = Machine::from_file("input/day5.txt").unwrap();
+    let mut machine = initial.clone();
+    machine.run_to_halt(vec![1]).unwrap();
     assert_eq(
         Day::new(5, Part::A),
         11_049_715,
         machine.values.pop_back().unwrap(),
     );
 
-    let mut machine = Machine::init(&initial.positions);
-    machine.run(vec![5]);
+    let mut machine = initial.clone();
+    machine.run_to_halt(vec![5]).unwrap();
     assert_eq(
         Day::new(5, Part::B),
         2_140_710,
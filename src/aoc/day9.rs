@@ -2,14 +2,14 @@ use super::assert::*;
 use super::intcode::Machine;
 
 pub fn solve() {
-    let initial = Machine::from_file("input/day9.txt");
-    let mut machine = Machine::init(&initial.positions);
-    machine.run(vec![1]);
+    let initial = Machine::from_file("input/day9.txt").unwrap();
+    let mut machine = initial.clone();
+    machine.run_to_halt(vec![1]).unwrap();
 
     assert_eq(Day::new(9, Part::A), 3_906_448_201, machine.values[0]);
 
-    let mut machine = Machine::init(&initial.positions);
-    machine.run(vec![2]);
+    let mut machine = initial.clone();
+    machine.run_to_halt(vec![2]).unwrap();
 
     assert_eq(Day::new(9, Part::B), 59_785, machine.values[0]);
 }
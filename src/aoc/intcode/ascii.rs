@@ -0,0 +1,99 @@
+use super::memory::{DenseMemory, Memory};
+use super::{IntcodeError, Machine, RunState};
+
+// Wraps a machine that talks in ASCII text. Outputs outside the ASCII range
+// (scores, counts) are kept apart from the text instead of being mangled
+// into characters.
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AsciiOutput {
+    pub text: String,
+    pub numbers: Vec<i64>,
+}
+
+fn ascii(value: i64) -> Option<char> {
+    if (0..128).contains(&value) {
+        Some(value as u8 as char)
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AsciiMachine<M: Memory = DenseMemory> {
+    pub machine: Machine<M>,
+
+    // Non-ASCII values seen by `read_line`, handed out with the next full read.
+    numbers: Vec<i64>,
+}
+
+impl<M: Memory> AsciiMachine<M> {
+    pub fn new(machine: Machine<M>) -> Self {
+        Self {
+            machine,
+            numbers: Vec::new(),
+        }
+    }
+
+    pub fn halted(&self) -> bool {
+        self.machine.halted
+    }
+
+    // Queues the line and a newline. Nothing runs until the next read.
+    pub fn send_line(&mut self, line: &str) {
+        self.machine.inputs.extend(line.chars().map(|c| c as i64));
+        self.machine.push_input('\n' as i64);
+    }
+
+    // Runs until the program asks for input, halts or hits a limit, and says
+    // which along with what it printed.
+    pub fn read_until_prompt(&mut self) -> Result<(AsciiOutput, RunState), IntcodeError> {
+        let state = self.machine.run(vec![])?;
+        Ok((self.take_output(), state))
+    }
+
+    // Runs a program that must finish with the input already sent.
+    pub fn read_to_end(&mut self) -> Result<AsciiOutput, IntcodeError> {
+        self.machine.run_to_halt(vec![])?;
+        Ok(self.take_output())
+    }
+
+    // The next line of text without its newline. `None` once the program
+    // stops with nothing left to say.
+    pub fn read_line(&mut self) -> Result<Option<String>, IntcodeError> {
+        let mut line = String::new();
+
+        loop {
+            let value = match self.machine.values.pop_front() {
+                Some(value) => value,
+                None => match self.machine.run_until_output()? {
+                    RunState::Output(value) => value,
+                    _ if line.is_empty() => return Ok(None),
+                    _ => return Ok(Some(line)),
+                },
+            };
+
+            match ascii(value) {
+                Some('\n') => return Ok(Some(line)),
+                Some(c) => line.push(c),
+                None => self.numbers.push(value),
+            }
+        }
+    }
+
+    fn take_output(&mut self) -> AsciiOutput {
+        let mut output = AsciiOutput {
+            text: String::new(),
+            numbers: self.numbers.drain(..).collect(),
+        };
+
+        for value in self.machine.values.drain(..) {
+            match ascii(value) {
+                Some(c) => output.text.push(c),
+                None => output.numbers.push(value),
+            }
+        }
+
+        output
+    }
+}
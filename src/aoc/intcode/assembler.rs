@@ -0,0 +1,332 @@
+use super::instruction::{Instruction, Mode, Opcode, Param};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+// Syntax, one statement per line:
+//
+//   loop:   ADD [counter], #1, [counter]   ; position, immediate
+//           IN [rb+2]                      ; relative
+//           LOCAL total = 3                ; `[total]` now means `[rb+3]`
+//           OUT [total]
+//           JT #1, #loop                   ; labels resolve to addresses
+//   counter: DATA 0, "text", loop+2
+//
+// A numeric label such as `0004:` asserts the current address, which lets
+// a disassembler listing be assembled again unchanged.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl AssembleError {
+    fn new(line: usize, message: String) -> Self {
+        Self { line, message }
+    }
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssembleError {}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(i64),
+    Label(String, i64),
+}
+
+#[derive(Clone, Debug)]
+enum Statement {
+    Code(Opcode, Vec<(Mode, Expr)>),
+    Data(Vec<Expr>),
+}
+
+pub fn assemble(source: &str) -> Result<Vec<i64>, AssembleError> {
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    let mut locals: HashMap<String, i64> = HashMap::new();
+    let mut statements: Vec<(usize, usize, Statement)> = Vec::new();
+    let mut address = 0;
+
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        let error = |message: String| AssembleError::new(number, message);
+        let mut rest = strip_comment(line).trim();
+
+        while let Some((label, remainder)) = split_label(rest) {
+            if let Ok(expected) = label.parse::<usize>() {
+                if expected != address {
+                    return Err(error(format!(
+                        "Address {} expected but at {}",
+                        expected, address
+                    )));
+                }
+            } else if labels.insert(label.to_string(), address).is_some() {
+                return Err(error(format!("Duplicate label {}", label)));
+            }
+
+            rest = remainder.trim();
+        }
+
+        if rest.is_empty() {
+            continue;
+        }
+
+        let (keyword, operands) = match rest.find(char::is_whitespace) {
+            Some(i) => (&rest[..i], rest[i..].trim()),
+            None => (rest, ""),
+        };
+
+        let statement = if keyword.eq_ignore_ascii_case("LOCAL") {
+            let (name, offset) = parse_local(operands).map_err(error)?;
+            locals.insert(name, offset);
+            continue;
+        } else if keyword.eq_ignore_ascii_case("DATA") {
+            Statement::Data(parse_data(operands).map_err(error)?)
+        } else {
+            let opcode = Opcode::from_mnemonic(keyword)
+                .ok_or_else(|| error(format!("Unknown mnemonic {}", keyword)))?;
+            let params = split_operands(operands)
+                .iter()
+                .map(|o| parse_operand(o, &locals))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(error)?;
+
+            if params.len() != opcode.param_count() {
+                return Err(error(format!(
+                    "{} takes {} parameters, found {}",
+                    opcode.mnemonic(),
+                    opcode.param_count(),
+                    params.len()
+                )));
+            }
+
+            if let Some(arg_number) = opcode.write_param() {
+                if params[arg_number - 1].0 == Mode::Immediate {
+                    return Err(error(format!(
+                        "{} can't write to an immediate parameter",
+                        opcode.mnemonic()
+                    )));
+                }
+            }
+
+            Statement::Code(opcode, params)
+        };
+
+        let size = match &statement {
+            Statement::Code(opcode, _) => opcode.size(),
+            Statement::Data(values) => values.len(),
+        };
+
+        statements.push((number, address, statement));
+        address += size;
+    }
+
+    let mut program = Vec::with_capacity(address);
+
+    for (number, address, statement) in statements {
+        let resolve = |expr: &Expr| match expr {
+            Expr::Number(n) => Ok(*n),
+            Expr::Label(name, offset) => {
+                let error = |message: String| AssembleError::new(number, message);
+                let address = labels
+                    .get(name)
+                    .ok_or_else(|| error(format!("Unknown label {}", name)))?;
+
+                (*address as i64)
+                    .checked_add(*offset)
+                    .ok_or_else(|| error(format!("Label offset out of range in {}", name)))
+            }
+        };
+
+        match statement {
+            Statement::Code(opcode, params) => {
+                let params = params
+                    .iter()
+                    .map(|(mode, expr)| Ok(Param::new(*mode, resolve(expr)?)))
+                    .collect::<Result<Vec<_>, AssembleError>>()?;
+                let instruction = Instruction {
+                    address,
+                    opcode,
+                    params,
+                };
+                program.extend(instruction.encode());
+            }
+
+            Statement::Data(values) => {
+                for value in values.iter() {
+                    program.push(resolve(value)?);
+                }
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+pub fn to_program_text(program: &[i64]) -> String {
+    let values: Vec<String> = program.iter().map(|v| v.to_string()).collect();
+    values.join(",")
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let colon = text.find(':')?;
+    let label = text[..colon].trim();
+
+    if is_identifier(label) || (!label.is_empty() && label.chars().all(|c| c.is_ascii_digit())) {
+        Some((label, &text[colon + 1..]))
+    } else {
+        None
+    }
+}
+
+fn split_operands(text: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut in_string = false;
+
+    for c in text.chars() {
+        match c {
+            '"' => {
+                in_string = !in_string;
+                current.push(c);
+            }
+            ',' if !in_string => {
+                operands.push(current.trim().to_string());
+                current.clear();
+            }
+            _ => current.push(c),
+        }
+    }
+
+    if !current.trim().is_empty() || !operands.is_empty() {
+        operands.push(current.trim().to_string());
+    }
+
+    operands
+}
+
+fn parse_expr(text: &str) -> Result<Expr, String> {
+    let text = text.trim();
+
+    if let Ok(n) = text.parse::<i64>() {
+        return Ok(Expr::Number(n));
+    }
+
+    let split = text
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == '+' || *c == '-')
+        .map(|(i, _)| i);
+    let (name, offset) = match split {
+        Some(i) => {
+            let offset = text[i..]
+                .replace(' ', "")
+                .trim_start_matches('+')
+                .parse::<i64>()
+                .map_err(|_| format!("Invalid offset in {}", text))?;
+            (text[..i].trim(), offset)
+        }
+        None => (text, 0),
+    };
+
+    if is_identifier(name) {
+        Ok(Expr::Label(name.to_string(), offset))
+    } else {
+        Err(format!("Invalid value {}", text))
+    }
+}
+
+fn parse_operand(text: &str, locals: &HashMap<String, i64>) -> Result<(Mode, Expr), String> {
+    if let Some(value) = text.strip_prefix('#') {
+        return Ok((Mode::Immediate, parse_expr(value)?));
+    }
+
+    let inner = text
+        .strip_prefix('[')
+        .and_then(|t| t.strip_suffix(']'))
+        .map(|t| t.trim())
+        .ok_or_else(|| format!("Invalid operand {}", text))?;
+
+    if inner.len() >= 2 && inner[..2].eq_ignore_ascii_case("rb") {
+        let offset = inner[2..].replace(' ', "");
+        let offset = offset.trim_start_matches('+');
+
+        if offset.is_empty() {
+            return Ok((Mode::Relative, Expr::Number(0)));
+        }
+
+        return match locals.get(offset) {
+            Some(local) => Ok((Mode::Relative, Expr::Number(*local))),
+            None => Ok((Mode::Relative, parse_expr(offset)?)),
+        };
+    }
+
+    match locals.get(inner) {
+        Some(local) => Ok((Mode::Relative, Expr::Number(*local))),
+        None => Ok((Mode::Position, parse_expr(inner)?)),
+    }
+}
+
+fn parse_data(text: &str) -> Result<Vec<Expr>, String> {
+    let mut values = Vec::new();
+
+    for operand in split_operands(text) {
+        if let Some(string) = operand.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+            values.extend(string.chars().map(|c| Expr::Number(c as i64)));
+        } else {
+            values.push(parse_expr(&operand)?);
+        }
+    }
+
+    if values.is_empty() {
+        Err(String::from("DATA needs at least one value"))
+    } else {
+        Ok(values)
+    }
+}
+
+fn parse_local(text: &str) -> Result<(String, i64), String> {
+    let mut parts = text.splitn(2, '=');
+    let name = parts.next().unwrap_or("").trim();
+    let offset = parts
+        .next()
+        .and_then(|o| o.trim().parse::<i64>().ok())
+        .ok_or_else(|| format!("Expected LOCAL name = offset, found {}", text))?;
+
+    if is_identifier(name) {
+        Ok((name.to_string(), offset))
+    } else {
+        Err(format!("Invalid local name {}", name))
+    }
+}
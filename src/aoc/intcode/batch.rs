@@ -0,0 +1,153 @@
+use super::memory::{DenseMemory, Memory};
+use super::profiler;
+use super::{IntcodeError, Machine};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// Many independent runs of one program, spread over a pool of threads. Each
+// query gets a fresh clone of the program, so queries can't see each other,
+// and results come back in the order the queries were given.
+
+// Queries a worker claims at a time.
+const CHUNK: usize = 16;
+
+pub type QueryResult = Result<Vec<i64>, IntcodeError>;
+
+#[derive(Debug)]
+pub struct Batch<M: Memory = DenseMemory> {
+    program: Machine<M>,
+    threads: usize,
+
+    // Results of `run` by input, kept across calls when enabled.
+    cache: Option<Mutex<HashMap<Vec<i64>, QueryResult>>>,
+}
+
+impl<M: Memory + Send + Sync> Batch<M> {
+    // One thread per core until `set_threads` says otherwise.
+    pub fn new(program: &Machine<M>) -> Self {
+        let threads = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+
+        Self {
+            program: program.clone(),
+            threads,
+            cache: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    // Turning the cache off forgets everything in it.
+    #[allow(dead_code)]
+    pub fn set_cache(&mut self, enabled: bool) {
+        self.cache = if enabled {
+            Some(Mutex::new(HashMap::new()))
+        } else {
+            None
+        };
+    }
+
+    // Runs each input vector to a halt and returns what it wrote.
+    pub fn run(&self, inputs: &[Vec<i64>]) -> Vec<QueryResult> {
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return self.map(inputs, |machine, i| run_query(machine, i)),
+        };
+
+        let missing: Vec<Vec<i64>> = {
+            let cached = cache.lock().unwrap();
+            let mut missing: Vec<Vec<i64>> = inputs
+                .iter()
+                .filter(|i| !cached.contains_key(*i))
+                .cloned()
+                .collect();
+
+            missing.sort();
+            missing.dedup();
+            missing
+        };
+
+        let results = self.map(&missing, |machine, i| run_query(machine, i));
+        let mut cached = cache.lock().unwrap();
+        cached.extend(missing.into_iter().zip(results));
+
+        inputs.iter().map(|i| cached[i].clone()).collect()
+    }
+
+    // Calls `query` with a fresh copy of the program for every item.
+    pub fn map<Q, R, F>(&self, queries: &[Q], query: F) -> Vec<R>
+    where
+        Q: Sync,
+        R: Send,
+        F: Fn(Machine<M>, &Q) -> R + Sync,
+    {
+        let threads = self.threads.min(queries.len().div_ceil(CHUNK));
+
+        // Not worth a thread.
+        if threads <= 1 {
+            return queries
+                .iter()
+                .map(|q| query(self.program.clone(), q))
+                .collect();
+        }
+
+        let next = AtomicUsize::new(0);
+        let profiling = profiler::is_active();
+        let mut results: Vec<Option<R>> = queries.iter().map(|_| None).collect();
+
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        if profiling {
+                            profiler::start();
+                        }
+
+                        let mut done = Vec::new();
+
+                        loop {
+                            let start = next.fetch_add(CHUNK, Ordering::Relaxed);
+
+                            if start >= queries.len() {
+                                break;
+                            }
+
+                            let end = queries.len().min(start + CHUNK);
+
+                            for (index, q) in queries[start..end].iter().enumerate() {
+                                done.push((start + index, query(self.program.clone(), q)));
+                            }
+                        }
+
+                        (done, profiler::finish())
+                    })
+                })
+                .collect();
+
+            for worker in workers {
+                let (done, profile) = worker.join().unwrap();
+
+                for (index, result) in done {
+                    results[index] = Some(result);
+                }
+
+                if let Some(profile) = profile {
+                    profiler::with_active(|active| active.merge(&profile));
+                }
+            }
+        });
+
+        results.into_iter().map(|r| r.unwrap()).collect()
+    }
+}
+
+fn run_query<M: Memory>(mut machine: Machine<M>, inputs: &[i64]) -> QueryResult {
+    machine.run_to_halt(inputs.iter().cloned())?;
+    Ok(machine.values.into_iter().collect())
+}
@@ -0,0 +1,349 @@
+use super::instruction::{Instruction, Mode, Opcode};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+// Static control flow, found by following every path from address 0 instead of
+// sweeping linearly, so data between routines is never mistaken for code.
+//
+// Jump targets are only known when given in immediate mode. Anything else is
+// recorded as an indirect jump. Compiled Intcode calls a routine by storing an
+// immediate return address and jumping, and the routine returns through an
+// indirect jump. When a jump follows a write of its own next address, the code
+// after it is treated as the place the call returns to.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EdgeKind {
+    Fallthrough,
+    Jump,
+    Return,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub target: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub start: usize,
+    pub instructions: Vec<Instruction>,
+    pub successors: Vec<Edge>,
+}
+
+impl Block {
+    pub fn end(&self) -> usize {
+        self.instructions
+            .last()
+            .map(|i| i.address + i.size())
+            .unwrap_or(self.start)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cfg {
+    pub blocks: BTreeMap<usize, Block>,
+
+    // Addresses of jumps whose target is read from memory.
+    pub indirect_jumps: Vec<usize>,
+
+    // (instruction, target) for position mode writes into reachable code.
+    pub self_modifying_writes: Vec<(usize, usize)>,
+
+    // Reachable addresses that don't hold a valid instruction.
+    pub invalid: Vec<usize>,
+}
+
+fn is_jump(instruction: &Instruction) -> bool {
+    instruction.opcode == Opcode::JumpIfTrue || instruction.opcode == Opcode::JumpIfFalse
+}
+
+// Whether a jump with an immediate condition is always or never taken.
+fn condition(instruction: &Instruction) -> Option<bool> {
+    let test = instruction.params[0];
+
+    if test.mode != Mode::Immediate {
+        return None;
+    }
+
+    let nonzero = test.value != 0;
+
+    Some(if instruction.opcode == Opcode::JumpIfTrue {
+        nonzero
+    } else {
+        !nonzero
+    })
+}
+
+fn immediate_target(instruction: &Instruction) -> Option<usize> {
+    let target = instruction.params[1];
+
+    if target.mode == Mode::Immediate && target.value >= 0 {
+        Some(target.value as usize)
+    } else {
+        None
+    }
+}
+
+// The value of an `ADD` or `MUL` of two immediates.
+pub fn immediate_result(instruction: &Instruction) -> Option<i64> {
+    let params = &instruction.params;
+
+    if params.len() < 2 || params[..2].iter().any(|p| p.mode != Mode::Immediate) {
+        return None;
+    }
+
+    match instruction.opcode {
+        Opcode::Add => params[0].value.checked_add(params[1].value),
+        Opcode::Multiply => params[0].value.checked_mul(params[1].value),
+        _ => None,
+    }
+}
+
+// A write of the address just past `jump` into memory, right before it.
+fn is_call(previous: Option<&Instruction>, jump: &Instruction) -> bool {
+    let next = (jump.address + jump.size()) as i64;
+
+    match previous {
+        Some(p) if p.address + p.size() == jump.address => immediate_result(p) == Some(next),
+        _ => false,
+    }
+}
+
+// Edges leaving `instruction`, and whether it ends its block.
+fn successors(previous: Option<&Instruction>, instruction: &Instruction) -> (Vec<Edge>, bool) {
+    let next = instruction.address + instruction.size();
+    let fallthrough = Edge {
+        target: next,
+        kind: EdgeKind::Fallthrough,
+    };
+
+    if instruction.opcode == Opcode::Halt {
+        return (vec![], true);
+    }
+
+    if !is_jump(instruction) {
+        return (vec![fallthrough], false);
+    }
+
+    let mut edges = Vec::new();
+    let taken = condition(instruction);
+
+    if taken != Some(false) {
+        if let Some(target) = immediate_target(instruction) {
+            edges.push(Edge {
+                target,
+                kind: EdgeKind::Jump,
+            });
+        }
+    }
+
+    if taken != Some(true) {
+        edges.push(fallthrough);
+    } else if is_call(previous, instruction) {
+        edges.push(Edge {
+            target: next,
+            kind: EdgeKind::Return,
+        });
+    }
+
+    (edges, true)
+}
+
+pub fn analyze(program: &[i64]) -> Cfg {
+    analyze_from(program, &[0])
+}
+
+// Also follows paths from other addresses, such as routines only ever reached
+// through a pointer.
+pub fn analyze_from(program: &[i64], entries: &[usize]) -> Cfg {
+    let mut cfg = Cfg::default();
+    let mut instructions: BTreeMap<usize, Instruction> = BTreeMap::new();
+    let mut leaders: BTreeSet<usize> = entries.iter().cloned().collect();
+    let mut pending = entries.to_vec();
+    let mut invalid = BTreeSet::new();
+
+    // Find every reachable instruction and where blocks must start.
+    while let Some(start) = pending.pop() {
+        let mut address = start;
+        let mut previous: Option<Instruction> = None;
+
+        while !instructions.contains_key(&address) {
+            let instruction = match Instruction::decode(program, address) {
+                Some(instruction) => instruction,
+                None => {
+                    invalid.insert(address);
+                    break;
+                }
+            };
+
+            let (edges, ends_block) = successors(previous.as_ref(), &instruction);
+
+            if is_jump(&instruction)
+                && condition(&instruction) != Some(false)
+                && immediate_target(&instruction).is_none()
+            {
+                cfg.indirect_jumps.push(address);
+            }
+
+            instructions.insert(address, instruction.clone());
+
+            if !ends_block {
+                address += instruction.size();
+                previous = Some(instruction);
+                continue;
+            }
+
+            for edge in edges {
+                leaders.insert(edge.target);
+                pending.push(edge.target);
+            }
+
+            break;
+        }
+    }
+
+    let code: BTreeSet<usize> = instructions
+        .values()
+        .flat_map(|i| i.address..i.address + i.size())
+        .collect();
+
+    for instruction in instructions.values() {
+        if let Some(n) = instruction.opcode.write_param() {
+            let param = instruction.params[n - 1];
+
+            if param.mode == Mode::Position
+                && param.value >= 0
+                && code.contains(&(param.value as usize))
+            {
+                cfg.self_modifying_writes
+                    .push((instruction.address, param.value as usize));
+            }
+        }
+    }
+
+    // Split the instructions into blocks at each leader and after each jump.
+    let mut block: Option<Block> = None;
+    let mut previous: Option<&Instruction> = None;
+
+    for (address, instruction) in instructions.iter() {
+        let contiguous = previous.map(|p| p.address + p.size() == *address);
+
+        if leaders.contains(address) || contiguous != Some(true) {
+            if let Some(mut finished) = block.take() {
+                if contiguous == Some(true) {
+                    finished.successors.push(Edge {
+                        target: *address,
+                        kind: EdgeKind::Fallthrough,
+                    });
+                }
+
+                cfg.blocks.insert(finished.start, finished);
+            }
+        }
+
+        let current = block.get_or_insert_with(|| Block {
+            start: *address,
+            instructions: Vec::new(),
+            successors: Vec::new(),
+        });
+
+        current.instructions.push(instruction.clone());
+
+        let (edges, ends_block) = successors(previous, instruction);
+
+        if ends_block {
+            let mut finished = block.take().unwrap();
+            finished.successors = edges;
+            cfg.blocks.insert(finished.start, finished);
+        }
+
+        previous = Some(instruction);
+    }
+
+    if let Some(finished) = block {
+        cfg.blocks.insert(finished.start, finished);
+    }
+
+    cfg.indirect_jumps.sort_unstable();
+    cfg.invalid = invalid.into_iter().collect();
+    cfg
+}
+
+impl Cfg {
+    pub fn summary(&self) -> String {
+        let mut text = String::new();
+        let instructions: usize = self.blocks.values().map(|b| b.instructions.len()).sum();
+
+        writeln!(
+            text,
+            "{} blocks, {} instructions",
+            self.blocks.len(),
+            instructions
+        )
+        .unwrap();
+
+        for address in self.indirect_jumps.iter() {
+            writeln!(text, "{:04}: indirect jump", address).unwrap();
+        }
+
+        for (address, target) in self.self_modifying_writes.iter() {
+            writeln!(text, "{:04}: writes code at {:04}", address, target).unwrap();
+        }
+
+        for address in self.invalid.iter() {
+            writeln!(text, "{:04}: reachable but not an instruction", address).unwrap();
+        }
+
+        text
+    }
+
+    // Indirect jumps get a dashed edge to a shared `indirect` node, and blocks
+    // whose code is written at run time are filled.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        let modified: BTreeSet<usize> = self.self_modifying_writes.iter().map(|w| w.1).collect();
+
+        writeln!(dot, "digraph intcode {{").unwrap();
+        writeln!(dot, "  node [shape=box, fontname=\"monospace\"];").unwrap();
+
+        for block in self.blocks.values() {
+            let mut label = String::new();
+
+            for instruction in block.instructions.iter() {
+                write!(label, "{:04}: {}\\l", instruction.address, instruction).unwrap();
+            }
+
+            let style = if (block.start..block.end()).any(|a| modified.contains(&a)) {
+                ", style=filled, fillcolor=\"#ffdddd\""
+            } else {
+                ""
+            };
+
+            writeln!(dot, "  b{} [label=\"{}\"{}];", block.start, label, style).unwrap();
+
+            for edge in block.successors.iter() {
+                let attributes = match edge.kind {
+                    EdgeKind::Fallthrough => "",
+                    EdgeKind::Jump => " [color=blue]",
+                    EdgeKind::Return => " [style=dotted, label=\"return\"]",
+                };
+
+                writeln!(dot, "  b{} -> b{}{};", block.start, edge.target, attributes).unwrap();
+            }
+
+            let last = block.instructions.last().map(|i| i.address);
+
+            if last.is_some_and(|a| self.indirect_jumps.contains(&a)) {
+                writeln!(dot, "  b{} -> indirect [style=dashed];", block.start).unwrap();
+            }
+        }
+
+        if !self.indirect_jumps.is_empty() {
+            writeln!(dot, "  indirect [shape=diamond, label=\"?\"];").unwrap();
+        }
+
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
@@ -0,0 +1,241 @@
+use super::cfg;
+use super::disassembler::{Line, DATA_PER_LINE};
+use super::extension::mode;
+use super::instruction::{Instruction, Opcode};
+use super::interpreter::Interpreter;
+use super::memory::Memory;
+use super::{IntcodeError, Machine, RunState};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::ops::Range;
+
+// Which parts of a program a run exercised: how often each instruction ran,
+// and which cells instructions read or wrote through their parameters.
+// Immediate parameters and fetching the instruction itself aren't reads.
+//
+// Code is whatever ran plus whatever can be reached from it, so code that an
+// input never got to still shows up. Cells that are accessed but aren't code
+// are data.
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub executed: BTreeMap<usize, u64>,
+    pub read: BTreeSet<usize>,
+    pub written: BTreeSet<usize>,
+}
+
+impl Coverage {
+    // Adds up runs, such as the same program with different inputs.
+    #[allow(dead_code)]
+    pub fn merge(&mut self, other: &Coverage) {
+        for (address, count) in &other.executed {
+            *self.executed.entry(*address).or_insert(0) += count;
+        }
+
+        self.read.extend(&other.read);
+        self.written.extend(&other.written);
+    }
+
+    fn code(&self, program: &[i64]) -> BTreeMap<usize, Instruction> {
+        let mut entries = vec![0];
+        entries.extend(self.executed.keys());
+
+        cfg::analyze_from(program, &entries)
+            .blocks
+            .values()
+            .flat_map(|block| block.instructions.iter())
+            .map(|instruction| (instruction.address, instruction.clone()))
+            .collect()
+    }
+
+    fn flags(&self, cells: Range<usize>) -> &'static str {
+        let read = cells.clone().any(|c| self.read.contains(&c));
+        let written = cells.into_iter().any(|c| self.written.contains(&c));
+
+        match (read, written) {
+            (true, true) => "RW",
+            (true, false) => "R",
+            (false, true) => "W",
+            (false, false) => "",
+        }
+    }
+
+    // The program as it was before the run, each line with how often it ran
+    // ("-" for code that never did) and whether its cells were read or written.
+    pub fn listing(&self, program: &[i64]) -> String {
+        let code = self.code(program);
+        let mut text = String::new();
+        let mut address = 0;
+
+        while address < program.len() {
+            let count = self.executed.get(&address).map(|c| c.to_string());
+
+            if let Some(instruction) = code.get(&address) {
+                let end = address + instruction.size();
+                let count = count.unwrap_or_else(|| "-".to_string());
+                let line = Line::Code(instruction.clone());
+
+                writeln!(
+                    text,
+                    "{:>10} {:<2} {}",
+                    count,
+                    self.flags(address..end),
+                    line
+                )
+                .unwrap();
+                address = end;
+                continue;
+            }
+
+            // Only cells alike go on one line. Something that ran but doesn't
+            // decode was changed before it ran, and gets a line of its own.
+            let start = address;
+            let flags = self.flags(start..start + 1);
+            address += 1;
+
+            while count.is_none()
+                && address < program.len()
+                && address - start < DATA_PER_LINE
+                && !code.contains_key(&address)
+                && !self.executed.contains_key(&address)
+                && self.flags(address..address + 1) == flags
+            {
+                address += 1;
+            }
+
+            let line = Line::Data {
+                address: start,
+                values: program[start..address].to_vec(),
+            };
+
+            writeln!(
+                text,
+                "{:>10} {:<2} {}",
+                count.unwrap_or_default(),
+                flags,
+                line
+            )
+            .unwrap();
+        }
+
+        text
+    }
+
+    pub fn summary(&self, program: &[i64]) -> String {
+        let code = self.code(program);
+        let hit = code
+            .keys()
+            .filter(|a| self.executed.contains_key(a))
+            .count();
+
+        format!(
+            "{} of {} instructions executed, {} cells read, {} written",
+            hit,
+            code.len(),
+            self.read.len(),
+            self.written.len()
+        )
+    }
+
+    // LCOV with addresses for line numbers, one DA record per instruction.
+    pub fn to_lcov(&self, name: &str, program: &[i64]) -> String {
+        let code = self.code(program);
+        let mut text = String::new();
+        let mut hit = 0;
+
+        writeln!(text, "TN:\nSF:{}", name).unwrap();
+
+        for address in code.keys() {
+            let count = self.executed.get(address).cloned().unwrap_or(0);
+
+            if count > 0 {
+                hit += 1;
+            }
+
+            writeln!(text, "DA:{},{}", address, count).unwrap();
+        }
+
+        writeln!(text, "LF:{}\nLH:{}\nend_of_record", code.len(), hit).unwrap();
+        text
+    }
+}
+
+impl<M: Memory> Machine<M> {
+    // Coverage carries over into clones, like the journal.
+    pub fn start_coverage(&mut self) {
+        self.coverage = Some(Coverage::default());
+    }
+
+    #[allow(dead_code)]
+    pub fn stop_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub(super) fn covered_step(&mut self) -> Result<Option<RunState>, IntcodeError> {
+        let ip = self.ip;
+        let (reads, writes) = self.accesses();
+
+        let result = if self.journal.is_some() {
+            self.journaled_step()?
+        } else {
+            self.execute()?
+        };
+
+        if result == Some(RunState::NeedsInput) {
+            return Ok(result);
+        }
+
+        let coverage = self.coverage.as_mut().unwrap();
+        *coverage.executed.entry(ip).or_insert(0) += 1;
+        coverage.read.extend(reads);
+        coverage.written.extend(writes);
+
+        Ok(result)
+    }
+
+    // Cells the instruction at ip will read and write. Anything that can't be
+    // worked out here makes the instruction fail, so it is never recorded.
+    fn accesses(&self) -> (Vec<usize>, Vec<usize>) {
+        let word = self.read(self.ip);
+
+        // Extensions read every parameter, written ones included.
+        let (params, reads_written, written) = match Opcode::from_instruction(word) {
+            Some(opcode) => (
+                opcode.param_count(),
+                false,
+                opcode.write_param().into_iter().collect(),
+            ),
+            None => match self.extension(word) {
+                Some(extension) => (extension.params(), true, extension.writes().to_vec()),
+                None => (0, false, vec![]),
+            },
+        };
+
+        let mut reads = Vec::new();
+        let mut writes = Vec::new();
+
+        for position in 1..=params {
+            let mode = mode(word, position);
+            let is_written = written.contains(&position);
+
+            let address = match self.address(mode, position) {
+                Ok(address) if mode != 1 => address,
+                _ => continue,
+            };
+
+            if is_written {
+                writes.push(address);
+            }
+
+            if !is_written || reads_written {
+                reads.push(address);
+            }
+        }
+
+        (reads, writes)
+    }
+}
@@ -0,0 +1,351 @@
+use super::{IntcodeError, Machine, RunState};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{stdin, stdout, Write};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    Stepped,
+    Breakpoint(usize),
+    Watchpoint { address: usize, old: i64, new: i64 },
+    NeedsInput,
+    Halted,
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Stepped => write!(f, "Stepped"),
+            Stop::Breakpoint(ip) => write!(f, "Breakpoint at {}", ip),
+            Stop::Watchpoint { address, old, new } => {
+                write!(f, "Watchpoint [{}] changed {} -> {}", address, old, new)
+            }
+            Stop::NeedsInput => write!(f, "Waiting for input"),
+            Stop::Halted => write!(f, "Halted"),
+        }
+    }
+}
+
+type Hook = Box<dyn FnMut(&Machine, &Stop)>;
+
+pub struct Debugger {
+    pub machine: Machine,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<usize>,
+    hooks: Vec<Hook>,
+}
+
+impl Debugger {
+    pub fn new(machine: Machine) -> Self {
+        Self {
+            machine,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            hooks: Vec::new(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, ip: usize) {
+        self.breakpoints.insert(ip);
+    }
+
+    pub fn remove_breakpoint(&mut self, ip: usize) -> bool {
+        self.breakpoints.remove(&ip)
+    }
+
+    // Watched cells may be inside the program or in extended memory.
+    pub fn add_watchpoint(&mut self, address: usize) {
+        self.watchpoints.insert(address);
+    }
+
+    pub fn remove_watchpoint(&mut self, address: usize) -> bool {
+        self.watchpoints.remove(&address)
+    }
+
+    // Hooks see every stop, including single steps.
+    #[allow(dead_code)]
+    pub fn add_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&Machine, &Stop) + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+    }
+
+    pub fn step(&mut self) -> Result<Stop, IntcodeError> {
+        let before: Vec<(usize, i64)> = self
+            .watchpoints
+            .iter()
+            .map(|a| (*a, self.machine.read(*a)))
+            .collect();
+
+        let stop = match self.machine.step()? {
+            Some(RunState::Output(value)) => {
+                self.machine.values.push_back(value);
+                None
+            }
+            Some(RunState::NeedsInput) => Some(Stop::NeedsInput),
+            Some(RunState::Halted) => Some(Stop::Halted),
+
+            // Only runs check limits, single steps don't.
+            Some(RunState::Stopped(_)) | None => None,
+        };
+
+        let changed = before
+            .into_iter()
+            .map(|(address, old)| (address, old, self.machine.read(address)))
+            .find(|(_, old, new)| old != new);
+
+        let stop = match (stop, changed) {
+            (Some(stop), _) => stop,
+            (None, Some((address, old, new))) => Stop::Watchpoint { address, old, new },
+            (None, None) if self.breakpoints.contains(&self.machine.ip) => {
+                Stop::Breakpoint(self.machine.ip)
+            }
+            (None, None) => Stop::Stepped,
+        };
+
+        for hook in self.hooks.iter_mut() {
+            hook(&self.machine, &stop);
+        }
+
+        Ok(stop)
+    }
+
+    // Runs until something other than a plain step happens.
+    // Resuming while sitting on a breakpoint executes that instruction first.
+    pub fn resume(&mut self) -> Result<Stop, IntcodeError> {
+        loop {
+            match self.step()? {
+                Stop::Stepped => continue,
+                stop => return Ok(stop),
+            }
+        }
+    }
+
+    pub fn registers(&self) -> String {
+        format!(
+            "ip={} rb={} halted={}",
+            self.machine.ip, self.machine.relative_base, self.machine.halted
+        )
+    }
+
+    pub fn pending_io(&self) -> String {
+        format!(
+            "inputs={:?} outputs={:?}",
+            self.machine.inputs, self.machine.values
+        )
+    }
+
+    pub fn current_instruction(&self) -> String {
+        let ip = self.machine.ip;
+
+        match self.machine.instruction(ip) {
+            Some(instruction) => format!("{:04}: {}", ip, instruction),
+            None => format!("{:04}: DATA {}", ip, self.machine.read(ip)),
+        }
+    }
+
+    pub fn dump(&self) -> String {
+        format!(
+            "{}\n{}\n{}",
+            self.registers(),
+            self.pending_io(),
+            self.current_instruction()
+        )
+    }
+}
+
+const HELP: &str = "\
+s [n]          step n instructions
+u [n]          undo n instructions
+g <count>      go back to an earlier instruction count
+c              continue until a breakpoint, watchpoint, halt or input wait
+b <ip>         toggle a breakpoint
+w <address>    toggle a watchpoint
+i <values..>   queue integer inputs
+t <text>       queue a line of ASCII input
+o              print and clear pending outputs
+r              show registers and pending I/O
+j              show instruction count and I/O so far
+x <addr> [n]   examine n memory cells
+l [addr] [n]   list n instructions
+q              quit";
+
+// The most cells or instructions `x` and `l` show at once.
+const MAX_COUNT: usize = 10_000;
+
+fn parse_numbers<T: FromStr>(args: &[&str]) -> Option<Vec<T>> {
+    args.iter().map(|a| a.parse().ok()).collect()
+}
+
+fn print_outputs(machine: &mut Machine) {
+    let values: Vec<i64> = machine.values.drain(..).collect();
+    let is_text = values.iter().all(|v| *v >= 0 && *v < 128);
+
+    if is_text && !values.is_empty() {
+        let text: String = values.iter().map(|v| *v as u8 as char).collect();
+        println!("{}", text);
+    } else {
+        println!("{:?}", values);
+    }
+}
+
+fn report(result: Result<Stop, IntcodeError>, debugger: &Debugger) {
+    match result {
+        Ok(stop) => println!("{}\n{}", stop, debugger.current_instruction()),
+        Err(error) => println!("Error: {}", error),
+    }
+}
+
+pub fn repl(mut machine: Machine) {
+    machine.start_journal();
+
+    let mut debugger = Debugger::new(machine);
+    println!("{}", debugger.dump());
+
+    loop {
+        print!("(icdb) ");
+        stdout().flush().unwrap();
+
+        let mut line = String::new();
+
+        if stdin().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let args = words.get(1..).unwrap_or(&[]);
+
+        // Everything but inputs takes addresses and counts, which can't be negative.
+        let numbers = parse_numbers::<usize>(args);
+
+        match (words.first().cloned(), numbers.as_deref()) {
+            (None, _) => continue,
+
+            (Some("s"), Some([])) => report(debugger.step(), &debugger),
+
+            (Some("s"), Some([n])) => {
+                let mut result = debugger.step();
+
+                for _ in 1..*n {
+                    match result {
+                        Ok(Stop::Stepped) => result = debugger.step(),
+                        _ => break,
+                    }
+                }
+
+                report(result, &debugger);
+            }
+
+            (Some("u"), Some(args)) if args.len() <= 1 => {
+                let n = args.first().cloned().unwrap_or(1);
+                let undone = (0..n).take_while(|_| debugger.machine.step_back()).count();
+                println!("Undid {}\n{}", undone, debugger.current_instruction());
+            }
+
+            (Some("g"), Some([count])) => {
+                if debugger.machine.rewind_to(*count) {
+                    println!("{}", debugger.current_instruction());
+                } else {
+                    println!("Not reached yet");
+                }
+            }
+
+            (Some("c"), _) => report(debugger.resume(), &debugger),
+
+            (Some("b"), Some([ip])) => {
+                let ip = *ip;
+
+                if !debugger.remove_breakpoint(ip) {
+                    debugger.add_breakpoint(ip);
+                }
+
+                println!("Breakpoints: {:?}", debugger.breakpoints);
+            }
+
+            (Some("w"), Some([address])) => {
+                let address = *address;
+
+                if !debugger.remove_watchpoint(address) {
+                    debugger.add_watchpoint(address);
+                }
+
+                println!("Watchpoints: {:?}", debugger.watchpoints);
+            }
+
+            (Some("i"), _) => match parse_numbers::<i64>(args) {
+                Some(values) => debugger.machine.inputs.extend(values),
+                None => println!("{}", HELP),
+            },
+
+            (Some("t"), _) => {
+                let text = line.trim_start()[1..].trim();
+                let chars = text.chars().chain("\n".chars()).map(|c| c as i64);
+                debugger.machine.inputs.extend(chars);
+            }
+
+            (Some("o"), _) => print_outputs(&mut debugger.machine),
+
+            (Some("r"), _) => println!("{}", debugger.dump()),
+
+            (Some("j"), _) => {
+                if let Some(journal) = debugger.machine.journal() {
+                    println!("count={}", journal.len());
+                    println!("inputs={:?}", journal.inputs());
+                    println!("outputs={:?}", journal.outputs());
+                }
+            }
+
+            (Some("x"), Some(args)) if args.len() <= 2 => {
+                let start = args.first().cloned().unwrap_or(0);
+                let count = args.get(1).cloned().unwrap_or(8);
+
+                if count > MAX_COUNT {
+                    println!("At most {} at a time", MAX_COUNT);
+                    continue;
+                }
+
+                print!("{:04}: [", start);
+
+                for (n, address) in (start..start.saturating_add(count)).enumerate() {
+                    let separator = if n == 0 { "" } else { ", " };
+                    print!("{}{}", separator, debugger.machine.read(address));
+                }
+
+                println!("]");
+            }
+
+            (Some("l"), Some(args)) if args.len() <= 2 => {
+                let mut address = args.first().cloned().unwrap_or(debugger.machine.ip);
+                let count = args.get(1).cloned().unwrap_or(10);
+
+                if count > MAX_COUNT {
+                    println!("At most {} at a time", MAX_COUNT);
+                    continue;
+                }
+
+                for _ in 0..count {
+                    match debugger.machine.instruction(address) {
+                        Some(instruction) => {
+                            println!("{:04}: {}", address, instruction);
+                            address = address.saturating_add(instruction.size());
+                        }
+                        None => {
+                            println!("{:04}: DATA {}", address, debugger.machine.read(address));
+                            address = address.saturating_add(1);
+                        }
+                    }
+                }
+            }
+
+            (Some("q"), _) => break,
+
+            (Some(_), None) if parse_numbers::<i64>(args).is_some() => {
+                println!("Addresses and counts can't be negative")
+            }
+
+            _ => println!("{}", HELP),
+        }
+    }
+}
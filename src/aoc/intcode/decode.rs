@@ -0,0 +1,51 @@
+use super::instruction::Opcode;
+use super::{mode_arg1, mode_arg2, mode_arg3};
+use std::sync::Arc;
+
+// The opcode and parameter modes packed into one instruction word.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decoded {
+    pub word: i64,
+    pub opcode: Option<Opcode>,
+    pub modes: [i64; 3],
+}
+
+impl Decoded {
+    #[inline(always)]
+    pub fn new(word: i64) -> Self {
+        Self {
+            word,
+            opcode: Opcode::from_instruction(word),
+            modes: [mode_arg1(word), mode_arg2(word), mode_arg3(word)],
+        }
+    }
+}
+
+// Every word of the loaded program decoded up front and shared between clones.
+// Intcode rewrites its own code, so an entry is only used while the word at
+// its address still matches. Anything else is decoded on the spot.
+#[derive(Clone, Debug)]
+pub struct DecodeCache {
+    entries: Arc<[Decoded]>,
+}
+
+impl DecodeCache {
+    pub fn new(program: &[i64]) -> Self {
+        Self {
+            entries: program.iter().map(|word| Decoded::new(*word)).collect(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn shares(&self, other: &DecodeCache) -> bool {
+        Arc::ptr_eq(&self.entries, &other.entries)
+    }
+
+    #[inline(always)]
+    pub fn decode(&self, address: usize, word: i64) -> Decoded {
+        match self.entries.get(address) {
+            Some(decoded) if decoded.word == word => *decoded,
+            _ => Decoded::new(word),
+        }
+    }
+}
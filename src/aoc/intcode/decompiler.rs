@@ -0,0 +1,1478 @@
+use super::cfg::{self, Block, Cfg, EdgeKind};
+use super::instruction::{Instruction, Mode, Opcode, Param};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+
+// Pseudo-code for programs compiled the way the puzzle inputs are. A call
+// stores its return address at [rb+0] and its arguments at [rb+1].., then
+// jumps. The routine opens with `ARB #n`, which turns those cells into the
+// bottom of its frame, hands a result back in the first argument's cell, and
+// returns with `ARB #-n` and a jump through [rb+0].
+//
+// Frame cells read before they are written are parameters (`a1`..) and the
+// rest are locals (`l1`..). [rb+k] past the frame is `s<k>`, where arguments
+// and results pass, and any other cell is `mem[n]`. A write into an operand
+// of a later instruction in the same block becomes an indexed load or store,
+// or a call through a pointer. Loops come from back edges and if/else from
+// post-dominators. A comparison straight into a jump's condition is folded
+// into it, and anything that doesn't nest becomes a `goto`.
+
+// Stands for leaving the routine when finding post-dominators.
+const EXIT: usize = usize::MAX;
+
+// The largest `ARB #n` taken for a frame. Bigger ones only move the stack.
+const MAX_FRAME: i64 = 1 << 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Var {
+    Global(i64),
+    Param(usize),
+    Local(usize),
+
+    // [rb+k] past the frame.
+    Slot(i64),
+
+    // Any other offset from rb.
+    Frame(i64),
+}
+
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Var::Global(address) => write!(f, "mem[{}]", address),
+            Var::Param(n) => write!(f, "a{}", n),
+            Var::Local(n) => write!(f, "l{}", n),
+            Var::Slot(k) => write!(f, "s{}", k),
+            Var::Frame(offset) => write!(f, "rb[{}]", offset),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Less,
+    AtLeast,
+    Equal,
+    NotEqual,
+}
+
+impl Operator {
+    fn symbol(self) -> &'static str {
+        match self {
+            Operator::Add => "+",
+            Operator::Subtract => "-",
+            Operator::Multiply => "*",
+            Operator::Less => "<",
+            Operator::AtLeast => ">=",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+        }
+    }
+
+    fn precedence(self) -> u8 {
+        match self {
+            Operator::Multiply => 3,
+            Operator::Add | Operator::Subtract => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Const(i64),
+    Var(Var),
+
+    // rb itself, for relative operands computed at run time.
+    Base,
+    Load(Box<Expr>),
+    Input,
+    Minus(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary(op, _, _) => op.precedence(),
+            Expr::Minus(_) | Expr::Not(_) => 4,
+            _ => 5,
+        }
+    }
+
+    fn any(&self, f: &dyn Fn(&Expr) -> bool) -> bool {
+        f(self)
+            || match self {
+                Expr::Load(e) | Expr::Minus(e) | Expr::Not(e) => e.any(f),
+                Expr::Binary(_, a, b) => a.any(f) || b.any(f),
+                _ => false,
+            }
+    }
+
+    fn has_input(&self) -> bool {
+        self.any(&|e| *e == Expr::Input)
+    }
+
+    fn reads(&self, var: Var) -> bool {
+        self.any(&|e| *e == Expr::Var(var))
+    }
+
+    fn is_comparison(&self) -> bool {
+        matches!(self, Expr::Binary(op, _, _) if op.precedence() == 1)
+    }
+}
+
+fn operand(f: &mut fmt::Formatter<'_>, e: &Expr, precedence: u8) -> fmt::Result {
+    if e.precedence() < precedence {
+        write!(f, "({})", e)
+    } else {
+        write!(f, "{}", e)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Var(var) => write!(f, "{}", var),
+            Expr::Base => write!(f, "rb"),
+            Expr::Load(address) => write!(f, "mem[{}]", address),
+            Expr::Input => write!(f, "input()"),
+            Expr::Minus(e) => {
+                write!(f, "-")?;
+                operand(f, e, 5)
+            }
+            Expr::Not(e) => {
+                write!(f, "!")?;
+                operand(f, e, 5)
+            }
+            Expr::Binary(op, a, b) => {
+                operand(f, a, op.precedence())?;
+                write!(f, " {} ", op.symbol())?;
+                operand(f, b, op.precedence() + 1)
+            }
+        }
+    }
+}
+
+fn binary(op: Operator, a: Expr, b: Expr) -> Expr {
+    Expr::Binary(op, Box::new(a), Box::new(b))
+}
+
+fn add(a: Expr, b: Expr) -> Expr {
+    match (a, b) {
+        (Expr::Const(x), Expr::Const(y)) if x.checked_add(y).is_some() => Expr::Const(x + y),
+        (Expr::Const(0), e) | (e, Expr::Const(0)) => e,
+        (e, Expr::Const(y)) | (Expr::Const(y), e) if y < 0 && y != i64::MIN => {
+            binary(Operator::Subtract, e, Expr::Const(-y))
+        }
+        (e, Expr::Minus(n)) | (Expr::Minus(n), e) => binary(Operator::Subtract, e, *n),
+        (a, b) => binary(Operator::Add, a, b),
+    }
+}
+
+fn multiply(a: Expr, b: Expr) -> Expr {
+    match (a, b) {
+        (Expr::Const(x), Expr::Const(y)) if x.checked_mul(y).is_some() => Expr::Const(x * y),
+        (Expr::Const(0), _) | (_, Expr::Const(0)) => Expr::Const(0),
+        (Expr::Const(1), e) | (e, Expr::Const(1)) => e,
+        (Expr::Const(-1), e) | (e, Expr::Const(-1)) => minus(e),
+        (a, b) => binary(Operator::Multiply, a, b),
+    }
+}
+
+fn minus(e: Expr) -> Expr {
+    match e {
+        Expr::Const(x) if x != i64::MIN => Expr::Const(-x),
+        Expr::Minus(e) => *e,
+        Expr::Binary(Operator::Subtract, a, b) => Expr::Binary(Operator::Subtract, b, a),
+        e => Expr::Minus(Box::new(e)),
+    }
+}
+
+fn compare(op: Operator, a: Expr, b: Expr) -> Expr {
+    match (op, a, b) {
+        (Operator::Less, Expr::Const(x), Expr::Const(y)) => Expr::Const(i64::from(x < y)),
+        (Operator::Equal, Expr::Const(x), Expr::Const(y)) => Expr::Const(i64::from(x == y)),
+        (op, a, b) => binary(op, a, b),
+    }
+}
+
+// The opposite of a condition, which only has to agree on being zero or not.
+fn not(e: Expr) -> Expr {
+    match e {
+        Expr::Binary(op, a, b) => {
+            let opposite = match op {
+                Operator::Less => Operator::AtLeast,
+                Operator::AtLeast => Operator::Less,
+                Operator::Equal => Operator::NotEqual,
+                Operator::NotEqual => Operator::Equal,
+                op => return Expr::Not(Box::new(Expr::Binary(op, a, b))),
+            };
+
+            Expr::Binary(opposite, a, b)
+        }
+        Expr::Not(e) => *e,
+        Expr::Const(x) => Expr::Const(i64::from(x == 0)),
+        e => Expr::Not(Box::new(e)),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Stmt {
+    Assign(Expr, Expr),
+    Output(Expr),
+    Call {
+        target: Expr,
+        args: Vec<Expr>,
+        result: Option<Expr>,
+    },
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+
+    // `None` loops forever.
+    While(Option<Expr>, Vec<Stmt>),
+    Break,
+    Continue,
+    Return(Option<Expr>),
+    Halt,
+    Goto(usize),
+    Label(usize),
+    Jump(Expr),
+    AdjustBase(Expr),
+    Invalid(usize),
+}
+
+impl Stmt {
+    fn diverges(&self) -> bool {
+        match self {
+            Stmt::Break
+            | Stmt::Continue
+            | Stmt::Return(_)
+            | Stmt::Halt
+            | Stmt::Goto(_)
+            | Stmt::Jump(_)
+            | Stmt::Invalid(_) => true,
+            Stmt::If(_, then, otherwise) => diverges(then) && diverges(otherwise),
+            _ => false,
+        }
+    }
+}
+
+fn diverges(stmts: &[Stmt]) -> bool {
+    stmts.last().is_some_and(|s| s.diverges())
+}
+
+// How a block leaves, once its statements have run.
+#[derive(Clone, Debug)]
+enum Exit {
+    Next(usize),
+    Branch(Expr, usize, usize),
+    End,
+}
+
+struct Function {
+    entry: usize,
+
+    // n from the `ARB #n` the routine opens with, or 0.
+    frame: i64,
+    prologue: bool,
+    params: usize,
+    returns: bool,
+
+    // The routine's blocks, and where each goes next within it.
+    successors: BTreeMap<usize, Vec<usize>>,
+}
+
+impl Function {
+    fn is_prologue(&self, instruction: &Instruction) -> bool {
+        self.prologue && instruction.address == self.entry
+    }
+
+    fn is_epilogue(&self, instruction: &Instruction) -> bool {
+        let amount = instruction.params.first();
+
+        self.frame > 0
+            && instruction.opcode == Opcode::AdjustRelativeBase
+            && amount.is_some_and(|p| p.mode == Mode::Immediate && p.value == -self.frame)
+    }
+
+    // The frame cell a relative parameter refers to, counting up from the
+    // return address at 0. `shift` is how far rb has moved since the start.
+    // Offsets saturate, since one that far out can't reach a valid address.
+    fn slot(&self, param: Param, shift: i64) -> Option<usize> {
+        let offset = param.value.saturating_add(shift);
+        let slot = offset.saturating_add(self.frame);
+
+        if param.mode == Mode::Relative && offset < 0 && slot >= 1 {
+            Some(slot as usize)
+        } else {
+            None
+        }
+    }
+
+    fn var(&self, param: Param, shift: i64) -> Var {
+        let offset = param.value.saturating_add(shift);
+
+        match self.slot(param, shift) {
+            _ if param.mode != Mode::Relative => Var::Global(param.value),
+            Some(slot) if slot <= self.params => Var::Param(slot),
+            Some(slot) => Var::Local(slot - self.params),
+            None if offset >= 1 => Var::Slot(offset),
+            None => Var::Frame(offset),
+        }
+    }
+
+    fn result(&self) -> Option<Expr> {
+        if self.returns && self.frame >= 2 {
+            Some(Expr::Var(
+                self.var(Param::new(Mode::Relative, 1 - self.frame), 0),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+// The rb shift in effect at each instruction of a block.
+fn shifts(function: &Function, block: &Block) -> Vec<i64> {
+    let mut shift = 0;
+
+    block
+        .instructions
+        .iter()
+        .map(|instruction| {
+            let current = shift;
+
+            if function.is_epilogue(instruction) {
+                shift -= function.frame;
+            }
+
+            current
+        })
+        .collect()
+}
+
+fn reads(instruction: &Instruction) -> impl Iterator<Item = &Param> {
+    let write = instruction.opcode.write_param();
+
+    instruction
+        .params
+        .iter()
+        .enumerate()
+        .filter(move |(i, _)| write != Some(i + 1))
+        .map(|(_, p)| p)
+}
+
+fn writes(instruction: &Instruction) -> Option<Param> {
+    instruction
+        .opcode
+        .write_param()
+        .map(|n| instruction.params[n - 1])
+}
+
+// The cell a write lands in, if that's an operand of one of `later`.
+fn operand_cell(later: &[Instruction], write: Param) -> Option<usize> {
+    let cell = write.value as usize;
+    let is_operand = |i: &Instruction| i.address < cell && cell < i.address + i.size();
+
+    if write.mode == Mode::Position && write.value >= 0 && later.iter().any(is_operand) {
+        Some(cell)
+    } else {
+        None
+    }
+}
+
+fn is_call(block: &Block) -> bool {
+    block.successors.iter().any(|e| e.kind == EdgeKind::Return)
+}
+
+// Cooper, Harvey and Kennedy's iterative algorithm, giving each node reachable
+// from `root` along `next` its immediate dominator. The root maps to itself.
+fn dominators<F, G>(root: usize, next: F, previous: G) -> BTreeMap<usize, usize>
+where
+    F: Fn(usize) -> Vec<usize>,
+    G: Fn(usize) -> Vec<usize>,
+{
+    let mut visited = BTreeSet::new();
+    let mut order = Vec::new();
+    let mut stack = vec![(root, next(root), 0)];
+
+    visited.insert(root);
+
+    while let Some(top) = stack.last_mut() {
+        if top.2 < top.1.len() {
+            let node = top.1[top.2];
+            top.2 += 1;
+
+            if visited.insert(node) {
+                stack.push((node, next(node), 0));
+            }
+        } else {
+            order.push(top.0);
+            stack.pop();
+        }
+    }
+
+    let index: BTreeMap<usize, usize> = order.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+    let mut idom = BTreeMap::new();
+    let mut changed = true;
+
+    idom.insert(root, root);
+
+    while changed {
+        changed = false;
+
+        for node in order.iter().rev().skip(1) {
+            let mut new: Option<usize> = None;
+
+            for p in previous(*node) {
+                if !idom.contains_key(&p) {
+                    continue;
+                }
+
+                new = Some(match new {
+                    None => p,
+                    Some(mut a) => {
+                        let mut b = p;
+
+                        while a != b {
+                            while index[&a] < index[&b] {
+                                a = idom[&a];
+                            }
+
+                            while index[&b] < index[&a] {
+                                b = idom[&b];
+                            }
+                        }
+
+                        a
+                    }
+                });
+            }
+
+            if let Some(new) = new {
+                if idom.insert(*node, new) != Some(new) {
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    idom
+}
+
+fn dominates(idom: &BTreeMap<usize, usize>, a: usize, mut b: usize) -> bool {
+    loop {
+        if a == b {
+            return true;
+        }
+
+        match idom.get(&b) {
+            Some(&parent) if parent != b => b = parent,
+            _ => return false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Loop {
+    header: usize,
+    body: BTreeSet<usize>,
+    exit: Option<usize>,
+}
+
+// Nests the blocks of one routine into statements.
+struct Structurer {
+    lowered: BTreeMap<usize, (Vec<Stmt>, Exit)>,
+    loops: BTreeMap<usize, Loop>,
+    joins: BTreeMap<usize, usize>,
+    emitted: BTreeSet<usize>,
+    gotos: BTreeSet<usize>,
+}
+
+impl Structurer {
+    fn new(
+        entry: usize,
+        successors: &BTreeMap<usize, Vec<usize>>,
+        lowered: BTreeMap<usize, (Vec<Stmt>, Exit)>,
+    ) -> Self {
+        let mut predecessors: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+
+        for (node, targets) in successors.iter() {
+            for target in targets.iter() {
+                predecessors.entry(*target).or_default().push(*node);
+            }
+        }
+
+        let next = |n: usize| successors.get(&n).cloned().unwrap_or_default();
+        let previous = |n: usize| predecessors.get(&n).cloned().unwrap_or_default();
+        let idom = dominators(entry, next, previous);
+        let mut loops: BTreeMap<usize, Loop> = BTreeMap::new();
+
+        for (node, targets) in successors.iter() {
+            for header in targets.iter().filter(|h| dominates(&idom, **h, *node)) {
+                let body = &mut loops
+                    .entry(*header)
+                    .or_insert_with(|| Loop {
+                        header: *header,
+                        body: [*header].iter().cloned().collect(),
+                        exit: None,
+                    })
+                    .body;
+                let mut pending = vec![*node];
+
+                while let Some(n) = pending.pop() {
+                    if body.insert(n) {
+                        pending.extend(previous(n));
+                    }
+                }
+            }
+        }
+
+        // Routines can end anywhere their blocks have nowhere to go, and a
+        // loop nothing leaves counts as an end too, so code before and in it
+        // still gets post-dominators.
+        let exits: Vec<usize> = successors
+            .iter()
+            .filter(|(_, targets)| targets.is_empty())
+            .map(|(node, _)| *node)
+            .chain(
+                loops
+                    .values()
+                    .filter(|l| {
+                        l.body
+                            .iter()
+                            .all(|n| next(*n).iter().all(|t| l.body.contains(t)))
+                    })
+                    .map(|l| l.header),
+            )
+            .collect();
+
+        let joins = dominators(
+            EXIT,
+            |n| {
+                if n == EXIT {
+                    exits.clone()
+                } else {
+                    previous(n)
+                }
+            },
+            |n| {
+                let mut targets = next(n);
+
+                if exits.contains(&n) {
+                    targets.push(EXIT);
+                }
+
+                targets
+            },
+        );
+
+        for l in loops.values_mut() {
+            let targets: BTreeSet<usize> = l
+                .body
+                .iter()
+                .flat_map(|n| next(*n))
+                .filter(|t| !l.body.contains(t))
+                .collect();
+            let join = joins.get(&l.header).filter(|j| targets.contains(j));
+
+            // Prefer leaving to code that carries on over a block that ends
+            // the routine, which can simply be repeated where it's needed.
+            l.exit = join
+                .cloned()
+                .or_else(|| targets.iter().find(|t| !next(**t).is_empty()).cloned())
+                .or_else(|| targets.iter().next().cloned());
+        }
+
+        Self {
+            lowered,
+            loops,
+            joins,
+            emitted: BTreeSet::new(),
+            gotos: BTreeSet::new(),
+        }
+    }
+
+    fn join(&self, node: usize) -> Option<usize> {
+        self.joins.get(&node).cloned().filter(|j| *j != EXIT)
+    }
+
+    fn goto(&mut self, node: usize, out: &mut Vec<Stmt>) {
+        self.gotos.insert(node);
+        out.push(Stmt::Goto(node));
+    }
+
+    fn structure(&mut self, entry: usize) -> Vec<Stmt> {
+        let mut out = Vec::new();
+        self.region(Some(entry), None, None, &mut out);
+
+        // Whatever only a goto leads to goes after the rest.
+        while let Some(node) = self.gotos.difference(&self.emitted).next().cloned() {
+            self.region(Some(node), None, None, &mut out);
+        }
+
+        out
+    }
+
+    fn region(
+        &mut self,
+        mut node: Option<usize>,
+        stop: Option<usize>,
+        context: Option<&Loop>,
+        out: &mut Vec<Stmt>,
+    ) {
+        while let Some(n) = node {
+            if let Some(l) = context {
+                if n == l.header {
+                    out.push(Stmt::Continue);
+                    return;
+                }
+
+                if Some(n) == l.exit {
+                    out.push(Stmt::Break);
+                    return;
+                }
+            }
+
+            if Some(n) == stop {
+                return;
+            }
+
+            if !self.lowered.contains_key(&n) {
+                out.push(Stmt::Invalid(n));
+                return;
+            }
+
+            // Blocks that end the routine are repeated rather than jumped to.
+            let terminal = matches!(self.lowered[&n].1, Exit::End);
+            let outside = context.is_some_and(|l| !l.body.contains(&n));
+
+            if !terminal && (outside || self.emitted.contains(&n)) {
+                self.goto(n, out);
+                return;
+            }
+
+            node = if self.loops.contains_key(&n) && context.is_none_or(|l| l.header != n) {
+                self.structure_loop(n, out)
+            } else {
+                self.block(n, context, out)
+            };
+        }
+    }
+
+    fn structure_loop(&mut self, header: usize, out: &mut Vec<Stmt>) -> Option<usize> {
+        let l = self.loops[&header].clone();
+        let mut body = Vec::new();
+        let next = self.block(header, Some(&l), &mut body);
+
+        self.region(next, None, Some(&l), &mut body);
+        out.push(Stmt::While(None, body));
+        l.exit
+    }
+
+    // Emits one block and returns where to carry on.
+    fn block(&mut self, node: usize, context: Option<&Loop>, out: &mut Vec<Stmt>) -> Option<usize> {
+        let (stmts, exit) = self.lowered[&node].clone();
+
+        self.emitted.insert(node);
+        out.push(Stmt::Label(node));
+        out.extend(stmts);
+
+        match exit {
+            Exit::Next(target) => Some(target),
+            Exit::End => None,
+            Exit::Branch(condition, taken, not_taken) => {
+                let mut join = self.join(node);
+
+                // Both sides leave the loop body by `break` or `continue`.
+                if let Some(l) = context {
+                    if join.is_some_and(|j| j == l.header || !l.body.contains(&j)) {
+                        join = None;
+                    }
+                }
+
+                let mut then = Vec::new();
+                let mut otherwise = Vec::new();
+
+                self.region(Some(taken), join, context, &mut then);
+                self.region(Some(not_taken), join, context, &mut otherwise);
+                out.push(Stmt::If(condition, then, otherwise));
+                join
+            }
+        }
+    }
+}
+
+struct Decompiler<'a> {
+    program: &'a [i64],
+    cfg: Cfg,
+
+    // Cells some instruction writes in position mode.
+    written: BTreeSet<usize>,
+    functions: BTreeMap<usize, Function>,
+}
+
+impl<'a> Decompiler<'a> {
+    fn new(program: &'a [i64]) -> Self {
+        let mut entries: BTreeSet<usize> = [0].iter().cloned().collect();
+
+        // Routines found through pointers can hold more calls and pointers.
+        loop {
+            let list: Vec<usize> = entries.iter().cloned().collect();
+            let cfg = cfg::analyze_from(program, &list);
+            let written = cfg
+                .blocks
+                .values()
+                .flat_map(|b| b.instructions.iter())
+                .filter_map(writes)
+                .filter(|p| p.mode == Mode::Position && p.value >= 0)
+                .map(|p| p.value as usize)
+                .collect();
+
+            let mut decompiler = Self {
+                program,
+                cfg,
+                written,
+                functions: BTreeMap::new(),
+            };
+            let found = decompiler.entries();
+
+            if found.is_subset(&entries) {
+                entries.retain(|e| decompiler.cfg.blocks.contains_key(e));
+                decompiler.build(&entries);
+                return decompiler;
+            }
+
+            entries.extend(found);
+        }
+    }
+
+    // Called addresses, and addresses of routines used as values.
+    fn entries(&self) -> BTreeSet<usize> {
+        let mut entries: BTreeSet<usize> = [0].iter().cloned().collect();
+
+        for block in self.cfg.blocks.values() {
+            if let Some(target) = self.call_target(block) {
+                entries.insert(target);
+            }
+
+            for instruction in block.instructions.iter() {
+                match cfg::immediate_result(instruction) {
+                    Some(address) if self.is_routine(address) => {
+                        entries.insert(address as usize);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        entries
+    }
+
+    // A routine starts by claiming a frame.
+    fn is_routine(&self, address: i64) -> bool {
+        if address < 0 {
+            return false;
+        }
+
+        match Instruction::decode(self.program, address as usize) {
+            Some(i) => {
+                i.opcode == Opcode::AdjustRelativeBase
+                    && i.params[0].mode == Mode::Immediate
+                    && i.params[0].value > 0
+            }
+            None => false,
+        }
+    }
+
+    // The value of a cell nothing writes.
+    fn constant(&self, address: i64) -> Option<i64> {
+        if address < 0 || self.written.contains(&(address as usize)) {
+            return None;
+        }
+
+        self.program.get(address as usize).cloned()
+    }
+
+    fn call_target(&self, block: &Block) -> Option<usize> {
+        if !is_call(block) {
+            return None;
+        }
+
+        let target = block.instructions.last()?.params[1];
+
+        let address = match target.mode {
+            Mode::Immediate => Some(target.value),
+            Mode::Position => self.constant(target.value),
+            Mode::Relative => None,
+        };
+
+        address
+            .filter(|a| *a >= 0 && (*a as usize) < self.program.len())
+            .map(|a| a as usize)
+    }
+
+    fn build(&mut self, entries: &BTreeSet<usize>) {
+        for entry in entries.iter() {
+            let first = &self.cfg.blocks[entry].instructions[0];
+            let prologue = first.opcode == Opcode::AdjustRelativeBase
+                && first.params[0].mode == Mode::Immediate
+                && first.params[0].value > 0;
+
+            // The main program's `ARB` only moves the stack past the program.
+            let frame = if prologue && *entry != 0 && first.params[0].value <= MAX_FRAME {
+                first.params[0].value
+            } else {
+                0
+            };
+
+            let mut function = Function {
+                entry: *entry,
+                frame,
+                prologue,
+                params: 0,
+                returns: false,
+                successors: BTreeMap::new(),
+            };
+            let mut pending = vec![*entry];
+
+            while let Some(node) = pending.pop() {
+                if function.successors.contains_key(&node) {
+                    continue;
+                }
+
+                let block = &self.cfg.blocks[&node];
+                let targets: Vec<usize> = block
+                    .successors
+                    .iter()
+                    .filter(|e| !is_call(block) || e.kind == EdgeKind::Return)
+                    .map(|e| e.target)
+                    .filter(|t| self.cfg.blocks.contains_key(t))
+                    .filter(|t| *t == *entry || !entries.contains(t))
+                    .collect();
+
+                pending.extend(targets.iter());
+                function.successors.insert(node, targets);
+            }
+
+            function.params = self.params(&function);
+            self.functions.insert(*entry, function);
+        }
+
+        // A routine returns a value if a caller reads it.
+        for block in self.cfg.blocks.values() {
+            let target = match self.call_target(block) {
+                Some(target) => target,
+                None => continue,
+            };
+
+            let after = block.successors.iter().find(|e| e.kind == EdgeKind::Return);
+
+            if after.is_some_and(|e| self.reads_result(e.target)) {
+                if let Some(function) = self.functions.get_mut(&target) {
+                    function.returns = true;
+                }
+            }
+        }
+    }
+
+    // Frame cells live on entry, so the caller must have set them.
+    fn params(&self, function: &Function) -> usize {
+        let mut uses: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        let mut defs: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+
+        for node in function.successors.keys() {
+            let block = &self.cfg.blocks[node];
+            let used = uses.entry(*node).or_default();
+            let defined = defs.entry(*node).or_default();
+
+            for (instruction, shift) in block.instructions.iter().zip(shifts(function, block)) {
+                for param in reads(instruction) {
+                    if let Some(slot) = function.slot(*param, shift) {
+                        if !defined.contains(&slot) {
+                            used.insert(slot);
+                        }
+                    }
+                }
+
+                if let Some(slot) = writes(instruction).and_then(|p| function.slot(p, shift)) {
+                    defined.insert(slot);
+                }
+            }
+        }
+
+        let mut live: BTreeMap<usize, BTreeSet<usize>> = uses.clone();
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for (node, targets) in function.successors.iter() {
+                let mut new = uses[node].clone();
+
+                for target in targets.iter() {
+                    new.extend(live[target].difference(&defs[node]));
+                }
+
+                if new != live[node] {
+                    live.insert(*node, new);
+                    changed = true;
+                }
+            }
+        }
+
+        live[&function.entry]
+            .iter()
+            .next_back()
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    // Whether the code a call returns to reads [rb+1] before writing it.
+    fn reads_result(&self, address: usize) -> bool {
+        let block = match self.cfg.blocks.get(&address) {
+            Some(block) => block,
+            None => return false,
+        };
+
+        let result = |p: &Param| p.mode == Mode::Relative && p.value == 1;
+
+        for instruction in block.instructions.iter() {
+            if instruction.opcode == Opcode::AdjustRelativeBase {
+                return false;
+            }
+
+            if reads(instruction).any(result) {
+                return true;
+            }
+
+            if writes(instruction).is_some_and(|p| result(&p)) {
+                return false;
+            }
+        }
+
+        // Passed straight on to the next call.
+        self.call_target(block)
+            .and_then(|t| self.functions.get(&t))
+            .is_some_and(|f| f.params >= 1)
+    }
+
+    fn lower(&self, function: &Function, block: &Block) -> (Vec<Stmt>, Exit) {
+        let mut stmts = Vec::new();
+
+        // Values written into operands of instructions later in the block.
+        let mut operands: BTreeMap<usize, Expr> = BTreeMap::new();
+        let shifts = shifts(function, block);
+
+        for (index, instruction) in block.instructions.iter().enumerate() {
+            let shift = shifts[index];
+            let read = |k: usize| self.read(function, instruction, k, shift, &operands);
+
+            match instruction.opcode {
+                Opcode::AdjustRelativeBase => {
+                    let amount = instruction.params[0];
+                    let constant = amount.mode == Mode::Immediate;
+
+                    if !(function.is_prologue(instruction)
+                        || function.is_epilogue(instruction)
+                        || constant && amount.value == 0)
+                    {
+                        stmts.push(Stmt::AdjustBase(read(0)));
+                    }
+                }
+                Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equal => {
+                    let (a, b) = (read(0), read(1));
+                    let value = match instruction.opcode {
+                        Opcode::Add => add(a, b),
+                        Opcode::Multiply => multiply(a, b),
+                        Opcode::LessThan => compare(Operator::Less, a, b),
+                        _ => compare(Operator::Equal, a, b),
+                    };
+
+                    match operand_cell(&block.instructions[index + 1..], instruction.params[2]) {
+                        Some(cell) => {
+                            operands.insert(cell, value);
+                        }
+                        None => stmts.push(Stmt::Assign(read(2), value)),
+                    }
+                }
+                Opcode::Input => {
+                    match operand_cell(&block.instructions[index + 1..], instruction.params[0]) {
+                        Some(cell) => {
+                            operands.insert(cell, Expr::Input);
+                        }
+                        None => stmts.push(Stmt::Assign(read(0), Expr::Input)),
+                    }
+                }
+                Opcode::Output => stmts.push(Stmt::Output(read(0))),
+                Opcode::Halt => {
+                    stmts.push(Stmt::Halt);
+                    return (stmts, Exit::End);
+                }
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                    let target = match read(1) {
+                        Expr::Var(Var::Global(cell)) => match self.constant(cell) {
+                            Some(value) => Expr::Const(value),
+                            None => Expr::Var(Var::Global(cell)),
+                        },
+                        target => target,
+                    };
+
+                    return self.jump(function, block, instruction, read(0), target, stmts);
+                }
+            }
+        }
+
+        match block.successors.first() {
+            Some(edge) => self.transfer(function, Expr::Const(edge.target as i64), stmts),
+            None => {
+                stmts.push(Stmt::Invalid(block.end()));
+                (stmts, Exit::End)
+            }
+        }
+    }
+
+    fn read(
+        &self,
+        function: &Function,
+        instruction: &Instruction,
+        k: usize,
+        shift: i64,
+        operands: &BTreeMap<usize, Expr>,
+    ) -> Expr {
+        let param = instruction.params[k];
+
+        match (operands.get(&(instruction.address + 1 + k)), param.mode) {
+            (Some(value), Mode::Immediate) => value.clone(),
+            (Some(value), Mode::Position) => Expr::Load(Box::new(value.clone())),
+            (Some(value), Mode::Relative) => Expr::Load(Box::new(add(Expr::Base, value.clone()))),
+            (None, Mode::Immediate) => Expr::Const(param.value),
+            (None, _) => Expr::Var(function.var(param, shift)),
+        }
+    }
+
+    fn jump(
+        &self,
+        function: &Function,
+        block: &Block,
+        instruction: &Instruction,
+        test: Expr,
+        target: Expr,
+        mut stmts: Vec<Stmt>,
+    ) -> (Vec<Stmt>, Exit) {
+        if is_call(block) {
+            return self.call(function, block, target, stmts);
+        }
+
+        let next = instruction.address + instruction.size();
+        let if_true = instruction.opcode == Opcode::JumpIfTrue;
+        let mut condition = if if_true {
+            test.clone()
+        } else {
+            not(test.clone())
+        };
+
+        match condition {
+            Expr::Const(0) => return self.transfer(function, Expr::Const(next as i64), stmts),
+            Expr::Const(_) => return self.transfer(function, target, stmts),
+            _ => {}
+        }
+
+        if let Some(Stmt::Assign(place, value)) = stmts.last() {
+            if *place == test && value.is_comparison() {
+                condition = if if_true {
+                    value.clone()
+                } else {
+                    not(value.clone())
+                };
+                stmts.pop();
+            }
+        }
+
+        match self.transfer(function, target, Vec::new()) {
+            (jump, Exit::Next(taken)) if jump.is_empty() => {
+                (stmts, Exit::Branch(condition, taken, next))
+            }
+            (jump, _) => {
+                stmts.push(Stmt::If(condition, jump, Vec::new()));
+                self.transfer(function, Expr::Const(next as i64), stmts)
+            }
+        }
+    }
+
+    // Where control goes to `target`, unless it leaves the routine.
+    fn transfer(
+        &self,
+        function: &Function,
+        target: Expr,
+        mut stmts: Vec<Stmt>,
+    ) -> (Vec<Stmt>, Exit) {
+        match target {
+            Expr::Const(t) if t >= 0 && function.successors.contains_key(&(t as usize)) => {
+                (stmts, Exit::Next(t as usize))
+            }
+            Expr::Const(t) if t >= 0 && !self.functions.contains_key(&(t as usize)) => {
+                (stmts, Exit::Next(t as usize))
+            }
+            Expr::Var(Var::Frame(offset)) if offset + function.frame == 0 => {
+                stmts.push(Stmt::Return(function.result()));
+                (stmts, Exit::End)
+            }
+            target => {
+                stmts.push(Stmt::Jump(target));
+                (stmts, Exit::End)
+            }
+        }
+    }
+
+    fn call(
+        &self,
+        function: &Function,
+        block: &Block,
+        target: Expr,
+        mut stmts: Vec<Stmt>,
+    ) -> (Vec<Stmt>, Exit) {
+        let after = block
+            .successors
+            .iter()
+            .find(|e| e.kind == EdgeKind::Return)
+            .unwrap()
+            .target;
+
+        if let Some(Stmt::Assign(_, Expr::Const(address))) = stmts.last() {
+            if *address == after as i64 {
+                stmts.pop();
+            }
+        }
+
+        let callee = match target {
+            Expr::Const(t) if t >= 0 => self.functions.get(&(t as usize)),
+            _ => None,
+        };
+
+        // Through a pointer, only the caller can say if there's a result.
+        let returns = match (callee, &target) {
+            (Some(callee), _) => callee.returns,
+            (None, Expr::Const(_)) => false,
+            (None, _) => self.reads_result(after),
+        };
+
+        // Arguments are the writes to [rb+k] right before the call. One moves
+        // into the call unless that would change what it or another argument
+        // reads, or the order of inputs.
+        let mut start = stmts.len();
+
+        while start > 0 && matches!(&stmts[start - 1], Stmt::Assign(Expr::Var(Var::Slot(_)), _)) {
+            start -= 1;
+        }
+
+        let setup: Vec<(i64, Expr)> = stmts
+            .split_off(start)
+            .into_iter()
+            .filter_map(|s| match s {
+                Stmt::Assign(Expr::Var(Var::Slot(k)), value) => Some((k, value)),
+                _ => None,
+            })
+            .collect();
+
+        let inputs = setup.iter().filter(|(_, v)| v.has_input()).count();
+        let mut count = callee.map_or(0, |f| f.params as i64);
+
+        while setup.iter().any(|(k, _)| *k == count + 1) {
+            count += 1;
+        }
+
+        let mut args: BTreeMap<i64, Expr> = BTreeMap::new();
+
+        for (index, (k, value)) in setup.iter().enumerate() {
+            let movable = *k <= count
+                && setup[index + 1..].iter().all(|(j, _)| j != k)
+                && setup
+                    .iter()
+                    .all(|(j, v)| !v.reads(Var::Slot(*k)) && !value.reads(Var::Slot(*j)))
+                && (inputs <= 1 || !value.has_input());
+
+            if movable {
+                args.insert(*k, value.clone());
+            } else {
+                stmts.push(Stmt::Assign(Expr::Var(Var::Slot(*k)), value.clone()));
+            }
+        }
+
+        stmts.push(Stmt::Call {
+            target,
+            args: (1..=count)
+                .map(|k| args.remove(&k).unwrap_or(Expr::Var(Var::Slot(k))))
+                .collect(),
+            result: Some(Expr::Var(Var::Slot(1))).filter(|_| returns),
+        });
+
+        self.transfer(function, Expr::Const(after as i64), stmts)
+    }
+
+    fn name(&self, address: usize) -> String {
+        if address == 0 {
+            "main".to_string()
+        } else {
+            format!("f{:04}", address)
+        }
+    }
+
+    fn write_function(&self, text: &mut String, function: &Function) {
+        let lowered = function
+            .successors
+            .keys()
+            .map(|node| (*node, self.lower(function, &self.cfg.blocks[node])))
+            .collect();
+        let mut structurer = Structurer::new(function.entry, &function.successors, lowered);
+        let body = structurer.structure(function.entry);
+        let mut body = tidy(body, &structurer.gotos);
+
+        if body.last() == Some(&Stmt::Return(None)) {
+            body.pop();
+        }
+
+        let params: Vec<String> = (1..=function.params).map(|n| format!("a{}", n)).collect();
+        let locals: Vec<String> = (1..(function.frame as usize).saturating_sub(function.params))
+            .map(|n| format!("l{}", n))
+            .collect();
+
+        writeln!(
+            text,
+            "fn {}({}) {{",
+            self.name(function.entry),
+            params.join(", ")
+        )
+        .unwrap();
+
+        if !locals.is_empty() {
+            writeln!(text, "    var {};", locals.join(", ")).unwrap();
+        }
+
+        self.write_stmts(text, &body, 1);
+        writeln!(text, "}}").unwrap();
+    }
+
+    fn write_stmts(&self, text: &mut String, stmts: &[Stmt], depth: usize) {
+        let indent = "    ".repeat(depth);
+
+        for stmt in stmts.iter() {
+            match stmt {
+                Stmt::Assign(place, value) => writeln!(text, "{}{} = {};", indent, place, value),
+                Stmt::Output(value) => writeln!(text, "{}output({});", indent, value),
+                Stmt::Call {
+                    target,
+                    args,
+                    result,
+                } => {
+                    // Routines are passed by address, so name them.
+                    let args: Vec<String> = args
+                        .iter()
+                        .map(|a| match a {
+                            Expr::Const(t)
+                                if *t > 0 && self.functions.contains_key(&(*t as usize)) =>
+                            {
+                                self.name(*t as usize)
+                            }
+                            a => a.to_string(),
+                        })
+                        .collect();
+                    let result = match result {
+                        Some(place) => format!("{} = ", place),
+                        None => String::new(),
+                    };
+                    let callee = match target {
+                        Expr::Const(t)
+                            if *t >= 0 && self.functions.contains_key(&(*t as usize)) =>
+                        {
+                            self.name(*t as usize)
+                        }
+                        target => format!("(*{})", target),
+                    };
+
+                    writeln!(text, "{}{}{}({});", indent, result, callee, args.join(", "))
+                }
+                Stmt::If(condition, then, otherwise) => {
+                    writeln!(text, "{}if ({}) {{", indent, condition).unwrap();
+                    self.write_stmts(text, then, depth + 1);
+
+                    let mut otherwise = otherwise;
+
+                    // else-if chains stay flat.
+                    while let [Stmt::If(condition, then, rest)] = otherwise.as_slice() {
+                        writeln!(text, "{}}} else if ({}) {{", indent, condition).unwrap();
+                        self.write_stmts(text, then, depth + 1);
+                        otherwise = rest;
+                    }
+
+                    if !otherwise.is_empty() {
+                        writeln!(text, "{}}} else {{", indent).unwrap();
+                        self.write_stmts(text, otherwise, depth + 1);
+                    }
+
+                    writeln!(text, "{}}}", indent)
+                }
+                Stmt::While(condition, body) => {
+                    match condition {
+                        Some(condition) => writeln!(text, "{}while ({}) {{", indent, condition),
+                        None => writeln!(text, "{}while (true) {{", indent),
+                    }
+                    .unwrap();
+                    self.write_stmts(text, body, depth + 1);
+                    writeln!(text, "{}}}", indent)
+                }
+                Stmt::Break => writeln!(text, "{}break;", indent),
+                Stmt::Continue => writeln!(text, "{}continue;", indent),
+                Stmt::Return(Some(value)) => writeln!(text, "{}return {};", indent, value),
+                Stmt::Return(None) => writeln!(text, "{}return;", indent),
+                Stmt::Halt => writeln!(text, "{}halt;", indent),
+                Stmt::Goto(address) => writeln!(text, "{}goto L{:04};", indent, address),
+                Stmt::Label(address) => writeln!(text, "L{:04}:", address),
+                Stmt::Jump(Expr::Const(t))
+                    if *t >= 0 && self.functions.contains_key(&(*t as usize)) =>
+                {
+                    writeln!(text, "{}goto {};", indent, self.name(*t as usize))
+                }
+                Stmt::Jump(target) => writeln!(text, "{}goto *{};", indent, target),
+                Stmt::AdjustBase(amount) => writeln!(text, "{}rb += {};", indent, amount),
+                Stmt::Invalid(address) => {
+                    writeln!(
+                        text,
+                        "{}fault;  // {:04} is not an instruction",
+                        indent, address
+                    )
+                }
+            }
+            .unwrap();
+        }
+    }
+}
+
+// Drops unused labels and dead code, gives calls the place their result is
+// copied to, and turns loops that test first into `while` conditions.
+fn tidy(stmts: Vec<Stmt>, labels: &BTreeSet<usize>) -> Vec<Stmt> {
+    let mut out: Vec<Stmt> = Vec::new();
+    let folds: Vec<bool> = (0..stmts.len())
+        .map(|i| overwrites_result(&stmts[i + 1..], labels))
+        .collect();
+
+    for (stmt, fold) in stmts.into_iter().zip(folds) {
+        let reachable = !diverges(&out);
+
+        match stmt {
+            Stmt::Label(n) if labels.contains(&n) => out.push(Stmt::Label(n)),
+            Stmt::Label(_) => {}
+            _ if !reachable => {}
+            Stmt::Assign(place, value) if place == value => {}
+            Stmt::If(condition, then, otherwise) => {
+                let then = tidy(then, labels);
+                let otherwise = tidy(otherwise, labels);
+
+                if then.is_empty() && otherwise.is_empty() {
+                    continue;
+                }
+
+                if then.is_empty() || diverges(&otherwise) && otherwise.len() <= then.len() {
+                    out.push(Stmt::If(not(condition), otherwise, Vec::new()));
+                    out.extend(then);
+                } else if diverges(&then) {
+                    out.push(Stmt::If(condition, then, Vec::new()));
+                    out.extend(otherwise);
+                } else {
+                    out.push(Stmt::If(condition, then, otherwise));
+                }
+            }
+            Stmt::While(condition, body) => {
+                let mut body = tidy(body, labels);
+
+                if body.last() == Some(&Stmt::Continue) {
+                    body.pop();
+                }
+
+                match (condition, body.first()) {
+                    (None, Some(Stmt::If(test, then, otherwise)))
+                        if *then == [Stmt::Break] && otherwise.is_empty() =>
+                    {
+                        let test = not(test.clone());
+                        body.remove(0);
+                        out.push(Stmt::While(Some(test), body));
+                    }
+                    (condition, _) => out.push(Stmt::While(condition, body)),
+                }
+            }
+            Stmt::Assign(place, Expr::Var(Var::Slot(1))) => match out.last_mut() {
+                Some(Stmt::Call { result, .. })
+                    if fold && *result == Some(Expr::Var(Var::Slot(1))) =>
+                {
+                    *result = Some(place);
+                }
+                _ => out.push(Stmt::Assign(place, Expr::Var(Var::Slot(1)))),
+            },
+            stmt => out.push(stmt),
+        }
+    }
+
+    out
+}
+
+// Whether `s1` is written again before anything could read it, so a call's
+// result can go straight to where it's copied.
+fn overwrites_result(stmts: &[Stmt], labels: &BTreeSet<usize>) -> bool {
+    let result = Expr::Var(Var::Slot(1));
+    let slot = Var::Slot(1);
+
+    for stmt in stmts.iter() {
+        match stmt {
+            Stmt::Label(n) if !labels.contains(n) => {}
+            Stmt::Assign(place, value) => {
+                if value.reads(slot) || place != &result && place.reads(slot) {
+                    return false;
+                }
+
+                if *place == result {
+                    return true;
+                }
+            }
+            Stmt::Call {
+                target,
+                args,
+                result: written,
+            } => {
+                let direct = matches!(target, Expr::Const(_));
+
+                if !direct || target.reads(slot) || args.iter().any(|a| a.reads(slot)) {
+                    return false;
+                }
+
+                if written.as_ref() == Some(&result) {
+                    return true;
+                }
+            }
+            Stmt::Output(value) if !value.reads(slot) => {}
+            Stmt::Return(_) | Stmt::Halt => return true,
+            _ => return false,
+        }
+    }
+
+    false
+}
+
+pub fn decompile(program: &[i64]) -> String {
+    let decompiler = Decompiler::new(program);
+    let mut text = String::new();
+
+    for (index, function) in decompiler.functions.values().enumerate() {
+        if index > 0 {
+            writeln!(text).unwrap();
+        }
+
+        decompiler.write_function(&mut text, function);
+    }
+
+    text
+}
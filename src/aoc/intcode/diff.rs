@@ -0,0 +1,146 @@
+use super::memory::Memory;
+use super::{IntcodeError, Machine, RunState};
+use std::collections::BTreeSet;
+use std::fmt;
+
+// Cells that differ between two machine states, merged into runs of adjacent
+// addresses. A run never crosses from the program into extended memory, which
+// is measured against the program length of the earlier state.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    Program,
+    Extended,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Range {
+    pub region: Region,
+    pub start: usize,
+    pub old: Vec<i64>,
+    pub new: Vec<i64>,
+}
+
+impl Range {
+    pub fn end(&self) -> usize {
+        self.start + self.old.len()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryDiff {
+    pub ranges: Vec<Range>,
+}
+
+impl MemoryDiff {
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    // Number of changed cells.
+    pub fn len(&self) -> usize {
+        self.ranges.iter().map(|r| r.old.len()).sum()
+    }
+
+    fn push(&mut self, region: Region, address: usize, old: i64, new: i64) {
+        if let Some(last) = self.ranges.last_mut() {
+            if last.region == region && last.end() == address {
+                last.old.push(old);
+                last.new.push(new);
+                return;
+            }
+        }
+
+        self.ranges.push(Range {
+            region,
+            start: address,
+            old: vec![old],
+            new: vec![new],
+        });
+    }
+}
+
+impl fmt::Display for MemoryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+
+        for range in self.ranges.iter() {
+            let region = match range.region {
+                Region::Program => "",
+                Region::Extended => " (extended)",
+            };
+
+            writeln!(
+                f,
+                "{:04}..{:04}{}: {:?} -> {:?}",
+                range.start,
+                range.end(),
+                region,
+                range.old,
+                range.new
+            )?;
+        }
+
+        writeln!(
+            f,
+            "{} cells changed in {} ranges",
+            self.len(),
+            self.ranges.len()
+        )
+    }
+}
+
+pub fn diff<M: Memory, N: Memory>(before: &Machine<M>, after: &Machine<N>) -> MemoryDiff {
+    let mut diff = MemoryDiff::default();
+    let program_len = before.memory.program_len();
+    let compared = program_len.max(after.memory.program_len());
+
+    for address in 0..compared {
+        let (old, new) = (before.read(address), after.read(address));
+
+        if old != new {
+            let region = if address < program_len {
+                Region::Program
+            } else {
+                Region::Extended
+            };
+
+            diff.push(region, address, old, new);
+        }
+    }
+
+    // Everything else is zero in both unless one of them lists it.
+    let extended: BTreeSet<usize> = before
+        .memory
+        .extended()
+        .into_iter()
+        .chain(after.memory.extended())
+        .map(|(address, _)| address)
+        .filter(|address| *address >= compared)
+        .collect();
+
+    for address in extended {
+        let (old, new) = (before.read(address), after.read(address));
+
+        if old != new {
+            diff.push(Region::Extended, address, old, new);
+        }
+    }
+
+    diff
+}
+
+impl<M: Memory> Machine<M> {
+    // Where running with `inputs` stopped, and what it changed in memory.
+    #[allow(dead_code)]
+    pub fn run_diff<I>(&mut self, inputs: I) -> Result<(RunState, MemoryDiff), IntcodeError>
+    where
+        I: IntoIterator<Item = i64>,
+    {
+        let before = self.clone();
+        let state = self.run(inputs)?;
+        Ok((state, diff(&before, self)))
+    }
+}
@@ -0,0 +1,57 @@
+use super::instruction::Instruction;
+use std::fmt;
+
+pub(super) const DATA_PER_LINE: usize = 8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Line {
+    Code(Instruction),
+    Data { address: usize, values: Vec<i64> },
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Line::Code(instruction) => write!(f, "{:04}: {}", instruction.address, instruction),
+            Line::Data { address, values } => {
+                let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                write!(f, "{:04}: DATA {}", address, values.join(", "))
+            }
+        }
+    }
+}
+
+// Linear sweep. Any cell that doesn't start a valid instruction becomes data.
+pub fn disassemble(program: &[i64]) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut address = 0;
+
+    while address < program.len() {
+        if let Some(instruction) = Instruction::decode(program, address) {
+            address += instruction.size();
+            lines.push(Line::Code(instruction));
+            continue;
+        }
+
+        match lines.last_mut() {
+            Some(Line::Data { values, .. }) if values.len() < DATA_PER_LINE => {
+                values.push(program[address])
+            }
+            _ => lines.push(Line::Data {
+                address,
+                values: vec![program[address]],
+            }),
+        }
+
+        address += 1;
+    }
+
+    lines
+}
+
+pub fn listing(program: &[i64]) -> String {
+    disassemble(program)
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
@@ -0,0 +1,257 @@
+use super::instruction::Opcode;
+use super::interpreter::Interpreter;
+use super::memory::Memory;
+use super::{IntcodeError, Machine, RunState};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+// Extra opcodes for Intcode dialects. An extension only says how many
+// parameters it takes and which of them it writes. The machine decodes the
+// modes, reads the parameters and stores the written ones, so extensions get
+// relative and position mode for free. Opcodes nothing claims are still errors.
+
+// Modes of any later parameters wouldn't fit in an instruction word.
+const MAX_PARAMS: usize = 17;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Output(i64),
+    Jump(i64),
+    Halt,
+
+    // Halts and leaves the code in `Machine::exit_code`.
+    Exit(i64),
+}
+
+pub trait Extension: Send + Sync {
+    // The two low digits of the instruction word.
+    fn opcode(&self) -> i64;
+
+    fn mnemonic(&self) -> &str;
+
+    fn params(&self) -> usize;
+
+    // 1-based positions of the parameters the extension writes.
+    fn writes(&self) -> &[usize] {
+        &[]
+    }
+
+    // `args` holds every parameter's value, and for written parameters the
+    // current value of the target cell. Whatever is left in the written slots
+    // is stored once this returns.
+    fn execute(&self, args: &mut [i64]) -> Action;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtensionError {
+    // Outside 1..=98 or already used by a standard instruction.
+    Reserved(i64),
+    Duplicate(i64),
+    TooManyParams { opcode: i64, params: usize },
+    InvalidWrite { opcode: i64, position: usize },
+}
+
+impl fmt::Display for ExtensionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtensionError::Reserved(opcode) => write!(f, "Opcode {} is reserved", opcode),
+            ExtensionError::Duplicate(opcode) => write!(f, "Opcode {} is already taken", opcode),
+            ExtensionError::TooManyParams { opcode, params } => write!(
+                f,
+                "Opcode {} takes {} parameters, more than {}",
+                opcode, params, MAX_PARAMS
+            ),
+            ExtensionError::InvalidWrite { opcode, position } => write!(
+                f,
+                "Opcode {} writes parameter {} but doesn't have it",
+                opcode, position
+            ),
+        }
+    }
+}
+
+impl Error for ExtensionError {}
+
+// Shared between clones of a machine.
+#[derive(Clone, Default)]
+pub struct Extensions {
+    table: BTreeMap<i64, Arc<dyn Extension>>,
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.table.iter().map(|(op, e)| (op, e.mnemonic())))
+            .finish()
+    }
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Extensions::default()
+    }
+
+    pub fn register<E: Extension + 'static>(&mut self, extension: E) -> Result<(), ExtensionError> {
+        let opcode = extension.opcode();
+
+        if !(1..=98).contains(&opcode) || Opcode::from_instruction(opcode).is_some() {
+            return Err(ExtensionError::Reserved(opcode));
+        }
+
+        if self.table.contains_key(&opcode) {
+            return Err(ExtensionError::Duplicate(opcode));
+        }
+
+        if extension.params() > MAX_PARAMS {
+            return Err(ExtensionError::TooManyParams {
+                opcode,
+                params: extension.params(),
+            });
+        }
+
+        if let Some(position) = extension
+            .writes()
+            .iter()
+            .find(|p| **p == 0 || **p > extension.params())
+        {
+            return Err(ExtensionError::InvalidWrite {
+                opcode,
+                position: *position,
+            });
+        }
+
+        self.table.insert(opcode, Arc::new(extension));
+        Ok(())
+    }
+
+    pub fn get(&self, opcode: i64) -> Option<&Arc<dyn Extension>> {
+        self.table.get(&opcode)
+    }
+}
+
+// Prints its parameter to stderr.
+pub struct DebugPrint(pub i64);
+
+impl Extension for DebugPrint {
+    fn opcode(&self) -> i64 {
+        self.0
+    }
+
+    fn mnemonic(&self) -> &str {
+        "DBG"
+    }
+
+    fn params(&self) -> usize {
+        1
+    }
+
+    fn execute(&self, args: &mut [i64]) -> Action {
+        eprintln!("{}", args[0]);
+        Action::Continue
+    }
+}
+
+// Halts with its parameter as the exit code.
+pub struct Exit(pub i64);
+
+impl Extension for Exit {
+    fn opcode(&self) -> i64 {
+        self.0
+    }
+
+    fn mnemonic(&self) -> &str {
+        "HLT"
+    }
+
+    fn params(&self) -> usize {
+        1
+    }
+
+    fn execute(&self, args: &mut [i64]) -> Action {
+        Action::Exit(args[0])
+    }
+}
+
+// The dialect the `run` command understands: DBG is opcode 20 and HLT is 21.
+pub fn dialect() -> Extensions {
+    let mut extensions = Extensions::new();
+    extensions.register(DebugPrint(20)).unwrap();
+    extensions.register(Exit(21)).unwrap();
+    extensions
+}
+
+pub(super) fn mode(word: i64, position: usize) -> i64 {
+    word / 10i64.pow(position as u32 + 1) % 10
+}
+
+impl<M: Memory> Machine<M> {
+    // Replaces any extensions registered before.
+    pub fn set_extensions(&mut self, extensions: Option<Extensions>) {
+        self.extensions = extensions;
+    }
+
+    // Set by an extension returning `Action::Exit`.
+    pub fn exit_code(&self) -> Option<i64> {
+        self.exit_code
+    }
+
+    pub(super) fn extension(&self, word: i64) -> Option<Arc<dyn Extension>> {
+        self.extensions.as_ref()?.get(word % 100).cloned()
+    }
+
+    pub(super) fn execute_extension(
+        &mut self,
+        extension: &dyn Extension,
+        word: i64,
+    ) -> Result<Option<RunState>, IntcodeError> {
+        let params = extension.params();
+        let writes = extension.writes();
+        let mut args = Vec::with_capacity(params);
+
+        // Everything that can fail is checked before the extension runs.
+        for position in 1..=params {
+            let mode = mode(word, position);
+
+            if mode == 1 && writes.contains(&position) {
+                return Err(
+                    self.error(|ip, instruction| IntcodeError::ImmediateWrite { ip, instruction })
+                );
+            }
+
+            let address = self.address(mode, position)?;
+            args.push(self.read(address));
+        }
+
+        let action = extension.execute(&mut args);
+
+        let next = match action {
+            Action::Jump(target) => self.checked_address(target)?,
+            Action::Halt | Action::Exit(_) => self.ip,
+            _ => self.ip + 1 + params,
+        };
+
+        for position in writes.iter() {
+            self.write_param(mode(word, *position), *position, args[position - 1])?;
+        }
+
+        self.ip = next;
+
+        match action {
+            Action::Continue | Action::Jump(_) => Ok(None),
+            Action::Output(value) => Ok(Some(RunState::Output(value))),
+            Action::Halt => {
+                self.halted = true;
+                Ok(Some(RunState::Halted))
+            }
+            Action::Exit(code) => {
+                self.exit_code = Some(code);
+                self.halted = true;
+                Ok(Some(RunState::Halted))
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use super::{mode_arg1, mode_arg2, mode_arg3};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Add,
+    Multiply,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equal,
+    AdjustRelativeBase,
+    Halt,
+}
+
+pub const OPCODES: [Opcode; 10] = [
+    Opcode::Add,
+    Opcode::Multiply,
+    Opcode::Input,
+    Opcode::Output,
+    Opcode::JumpIfTrue,
+    Opcode::JumpIfFalse,
+    Opcode::LessThan,
+    Opcode::Equal,
+    Opcode::AdjustRelativeBase,
+    Opcode::Halt,
+];
+
+impl Opcode {
+    // Ignores the parameter modes.
+    pub fn from_instruction(instruction: i64) -> Option<Self> {
+        match instruction % 100 {
+            1 => Some(Opcode::Add),
+            2 => Some(Opcode::Multiply),
+            3 => Some(Opcode::Input),
+            4 => Some(Opcode::Output),
+            5 => Some(Opcode::JumpIfTrue),
+            6 => Some(Opcode::JumpIfFalse),
+            7 => Some(Opcode::LessThan),
+            8 => Some(Opcode::Equal),
+            9 => Some(Opcode::AdjustRelativeBase),
+            99 => Some(Opcode::Halt),
+            _ => None,
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        OPCODES
+            .iter()
+            .find(|op| op.mnemonic().eq_ignore_ascii_case(mnemonic))
+            .cloned()
+    }
+
+    pub fn code(self) -> i64 {
+        match self {
+            Opcode::Add => 1,
+            Opcode::Multiply => 2,
+            Opcode::Input => 3,
+            Opcode::Output => 4,
+            Opcode::JumpIfTrue => 5,
+            Opcode::JumpIfFalse => 6,
+            Opcode::LessThan => 7,
+            Opcode::Equal => 8,
+            Opcode::AdjustRelativeBase => 9,
+            Opcode::Halt => 99,
+        }
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Add => "ADD",
+            Opcode::Multiply => "MUL",
+            Opcode::Input => "IN",
+            Opcode::Output => "OUT",
+            Opcode::JumpIfTrue => "JT",
+            Opcode::JumpIfFalse => "JF",
+            Opcode::LessThan => "LT",
+            Opcode::Equal => "EQ",
+            Opcode::AdjustRelativeBase => "ARB",
+            Opcode::Halt => "HLT",
+        }
+    }
+
+    pub fn param_count(self) -> usize {
+        match self {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equal => 3,
+            Opcode::JumpIfTrue | Opcode::JumpIfFalse => 2,
+            Opcode::Input | Opcode::Output | Opcode::AdjustRelativeBase => 1,
+            Opcode::Halt => 0,
+        }
+    }
+
+    // The parameter the instruction writes to, counting from 1 like `mode_arg1`.
+    pub fn write_param(self) -> Option<usize> {
+        match self {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equal => Some(3),
+            Opcode::Input => Some(1),
+            _ => None,
+        }
+    }
+
+    pub fn size(self) -> usize {
+        self.param_count() + 1
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl Mode {
+    pub fn from_code(code: i64) -> Option<Self> {
+        match code {
+            0 => Some(Mode::Position),
+            1 => Some(Mode::Immediate),
+            2 => Some(Mode::Relative),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> i64 {
+        match self {
+            Mode::Position => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2,
+        }
+    }
+}
+
+pub fn mode_code(instruction: i64, arg_number: usize) -> i64 {
+    match arg_number {
+        1 => mode_arg1(instruction),
+        2 => mode_arg2(instruction),
+        3 => mode_arg3(instruction),
+        _ => 0,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Param {
+    pub mode: Mode,
+    pub value: i64,
+}
+
+impl Param {
+    pub fn new(mode: Mode, value: i64) -> Self {
+        Self { mode, value }
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            Mode::Position => write!(f, "[{}]", self.value),
+            Mode::Immediate => write!(f, "#{}", self.value),
+            Mode::Relative if self.value < 0 => write!(f, "[rb{}]", self.value),
+            Mode::Relative => write!(f, "[rb+{}]", self.value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: usize,
+    pub opcode: Opcode,
+    pub params: Vec<Param>,
+}
+
+impl Instruction {
+    // Returns `None` for anything the interpreter would reject and for encodings that
+    // would not survive re-encoding, such as a mode digit on a parameter that doesn't exist.
+    pub fn decode(memory: &[i64], address: usize) -> Option<Self> {
+        let raw = *memory.get(address)?;
+
+        if raw < 0 {
+            return None;
+        }
+
+        let opcode = Opcode::from_instruction(raw)?;
+        let mut params = Vec::with_capacity(opcode.param_count());
+
+        for arg_number in 1..=opcode.param_count() {
+            let mode = Mode::from_code(mode_code(raw, arg_number))?;
+
+            if mode == Mode::Immediate && opcode.write_param() == Some(arg_number) {
+                return None;
+            }
+
+            params.push(Param::new(mode, *memory.get(address + arg_number)?));
+        }
+
+        let instruction = Instruction {
+            address,
+            opcode,
+            params,
+        };
+
+        if instruction.encode()[0] == raw {
+            Some(instruction)
+        } else {
+            None
+        }
+    }
+
+    pub fn encode(&self) -> Vec<i64> {
+        let modes = self
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, p)| p.mode.code() * 10_i64.pow(i as u32 + 2))
+            .sum::<i64>();
+        let mut words = vec![self.opcode.code() + modes];
+        words.extend(self.params.iter().map(|p| p.value));
+        words
+    }
+
+    pub fn size(&self) -> usize {
+        self.opcode.size()
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.opcode.mnemonic())?;
+
+        for (i, param) in self.params.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, param)?;
+        }
+
+        Ok(())
+    }
+}
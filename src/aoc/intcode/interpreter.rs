@@ -0,0 +1,206 @@
+use super::decode::Decoded;
+use super::instruction::Opcode;
+use super::numeric::Number;
+use super::{IntcodeError, RunState};
+
+// The instruction set, written once for every kind of machine. A machine
+// provides its registers, memory and I/O, and gets decoding, addressing,
+// arithmetic and errors from here, so `Machine` and `NumericMachine` can't
+// drift apart. Sums, products and the relative base are checked, and an
+// overflow is an error at the ip of the instruction.
+//
+// Addresses, the relative base and instruction words must fit in an `i64`
+// whatever the number type.
+
+pub(super) trait Interpreter {
+    type Value: Number;
+
+    fn ip(&self) -> usize;
+    fn set_ip(&mut self, ip: usize);
+    fn relative_base(&self) -> i64;
+    fn set_relative_base(&mut self, relative_base: i64);
+
+    fn get(&self, address: usize) -> Self::Value;
+    fn set(&mut self, address: usize, value: Self::Value);
+
+    // The next input, which is only consumed once the instruction succeeds.
+    fn peek_input(&self) -> Option<Self::Value>;
+    fn consume_input(&mut self);
+
+    // `None` to carry on, or the state to stop in.
+    fn output(&mut self, value: Self::Value) -> Option<RunState>;
+    fn halt(&mut self);
+
+    fn decode(&self, word: i64) -> Decoded {
+        Decoded::new(word)
+    }
+
+    // Called for opcodes outside the instruction set.
+    fn execute_unknown(&mut self, _word: i64) -> Result<Option<RunState>, IntcodeError> {
+        Err(self.error(|ip, instruction| IntcodeError::InvalidOpcode { ip, instruction }))
+    }
+
+    fn execute(&mut self) -> Result<Option<RunState>, IntcodeError> {
+        let word = match self.get(self.ip()).to_i64() {
+            Some(word) => word,
+            None => {
+                return Err(
+                    self.error(|ip, instruction| IntcodeError::InvalidOpcode { ip, instruction })
+                )
+            }
+        };
+        let decoded = self.decode(word);
+        let [mode1, mode2, mode3] = decoded.modes;
+        let ip = self.ip();
+
+        match decoded.opcode {
+            Some(Opcode::Add) => {
+                let arg1 = self.lookup(mode1, 1)?;
+                let arg2 = self.lookup(mode2, 2)?;
+                let sum = self.overflow_checked(arg1.checked_add(&arg2))?;
+                self.write_param(mode3, 3, sum)?;
+                self.set_ip(ip + 4);
+            }
+
+            Some(Opcode::Multiply) => {
+                let arg1 = self.lookup(mode1, 1)?;
+                let arg2 = self.lookup(mode2, 2)?;
+                let product = self.overflow_checked(arg1.checked_mul(&arg2))?;
+                self.write_param(mode3, 3, product)?;
+                self.set_ip(ip + 4);
+            }
+
+            Some(Opcode::Input) => match self.peek_input() {
+                Some(input) => {
+                    self.write_param(mode1, 1, input)?;
+                    self.consume_input();
+                    self.set_ip(ip + 2);
+                }
+                None => return Ok(Some(RunState::NeedsInput)),
+            },
+
+            Some(Opcode::Output) => {
+                let value = self.lookup(mode1, 1)?;
+                self.set_ip(ip + 2);
+                return Ok(self.output(value));
+            }
+
+            Some(Opcode::JumpIfTrue) | Some(Opcode::JumpIfFalse) => {
+                let test = self.lookup(mode1, 1)?;
+                let target = self.lookup(mode2, 2)?;
+                let jump_if = decoded.opcode == Some(Opcode::JumpIfTrue);
+
+                if test.is_zero() != jump_if {
+                    let target = self.to_address(&target)?;
+                    self.set_ip(target);
+                } else {
+                    self.set_ip(ip + 3);
+                }
+            }
+
+            Some(Opcode::LessThan) => {
+                let less = self.lookup(mode1, 1)? < self.lookup(mode2, 2)?;
+                self.write_param(mode3, 3, Self::Value::from(i64::from(less)))?;
+                self.set_ip(ip + 4);
+            }
+
+            Some(Opcode::Equal) => {
+                let equal = self.lookup(mode1, 1)? == self.lookup(mode2, 2)?;
+                self.write_param(mode3, 3, Self::Value::from(i64::from(equal)))?;
+                self.set_ip(ip + 4);
+            }
+
+            Some(Opcode::AdjustRelativeBase) => {
+                let adjustment = self.lookup(mode1, 1)?.to_i64();
+                let base = adjustment.and_then(|a| self.relative_base().checked_add(a));
+                let base = self.overflow_checked(base)?;
+                self.set_relative_base(base);
+                self.set_ip(ip + 2);
+            }
+
+            Some(Opcode::Halt) => {
+                self.halt();
+                return Ok(Some(RunState::Halted));
+            }
+
+            None => return self.execute_unknown(word),
+        }
+
+        Ok(None)
+    }
+
+    // An instruction word too big for an `i64` is reported as 0.
+    fn error<F>(&self, f: F) -> IntcodeError
+    where
+        F: FnOnce(usize, i64) -> IntcodeError,
+    {
+        f(self.ip(), self.get(self.ip()).to_i64().unwrap_or(0))
+    }
+
+    fn overflow_checked<T>(&self, value: Option<T>) -> Result<T, IntcodeError> {
+        value
+            .ok_or_else(|| self.error(|ip, instruction| IntcodeError::Overflow { ip, instruction }))
+    }
+
+    fn address(&self, mode: i64, arg_number: usize) -> Result<usize, IntcodeError> {
+        let parameter = self.get(self.ip() + arg_number);
+
+        match mode {
+            // Position
+            0 => self.to_address(&parameter),
+
+            // Value
+            1 => Ok(self.ip() + arg_number),
+
+            // Relative
+            2 => {
+                let offset = self.overflow_checked(parameter.to_i64())?;
+                let address = self.relative_base().checked_add(offset);
+                self.checked_address(self.overflow_checked(address)?)
+            }
+
+            _ => Err(self.error(|ip, instruction| IntcodeError::InvalidMode {
+                ip,
+                instruction,
+                mode,
+            })),
+        }
+    }
+
+    fn to_address(&self, value: &Self::Value) -> Result<usize, IntcodeError> {
+        self.checked_address(self.overflow_checked(value.to_i64())?)
+    }
+
+    fn checked_address(&self, address: i64) -> Result<usize, IntcodeError> {
+        if address < 0 {
+            Err(self.error(|ip, instruction| IntcodeError::NegativeAddress {
+                ip,
+                instruction,
+                address,
+            }))
+        } else {
+            Ok(address as usize)
+        }
+    }
+
+    fn lookup(&self, mode: i64, arg_number: usize) -> Result<Self::Value, IntcodeError> {
+        Ok(self.get(self.address(mode, arg_number)?))
+    }
+
+    fn write_param(
+        &mut self,
+        mode: i64,
+        arg_number: usize,
+        value: Self::Value,
+    ) -> Result<(), IntcodeError> {
+        if mode == 1 {
+            return Err(
+                self.error(|ip, instruction| IntcodeError::ImmediateWrite { ip, instruction })
+            );
+        }
+
+        let address = self.address(mode, arg_number)?;
+        self.set(address, value);
+        Ok(())
+    }
+}
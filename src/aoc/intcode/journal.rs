@@ -0,0 +1,171 @@
+use super::interpreter::Interpreter;
+use super::memory::Memory;
+use super::{IntcodeError, Machine, RunState};
+use std::mem;
+
+// What each executed instruction changed, enough to undo it: the ip, relative
+// base, halted flag and exit code before it ran, the old values of the cells it
+// wrote, and the input it consumed. Outputs are kept for reference only. Stepping back
+// never takes them out of `values`, since the caller may already have read them.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub ip: usize,
+    pub relative_base: i64,
+    pub halted: bool,
+    pub exit_code: Option<i64>,
+
+    // (address, previous value), in the order they were made. Only extensions
+    // write more than one cell.
+    pub writes: Vec<(usize, i64)>,
+    pub input: Option<i64>,
+    pub output: Option<i64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Journal {
+    entries: Vec<Entry>,
+
+    // Writes made by the instruction being executed.
+    pending: Vec<(usize, i64)>,
+}
+
+impl Journal {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    #[allow(dead_code)]
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn inputs(&self) -> Vec<i64> {
+        self.entries.iter().filter_map(|e| e.input).collect()
+    }
+
+    pub fn outputs(&self) -> Vec<i64> {
+        self.entries.iter().filter_map(|e| e.output).collect()
+    }
+
+    pub(super) fn note_write(&mut self, address: usize, previous: i64) {
+        self.pending.push((address, previous));
+    }
+}
+
+impl<M: Memory> Machine<M> {
+    // Starts recording from the current state, which becomes instruction 0.
+    pub fn start_journal(&mut self) {
+        self.journal = Some(Journal::default());
+    }
+
+    #[allow(dead_code)]
+    pub fn stop_journal(&mut self) -> Option<Journal> {
+        self.journal.take()
+    }
+
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    // Instructions executed since the journal was started.
+    pub fn instruction_count(&self) -> Option<usize> {
+        self.journal.as_ref().map(|j| j.len())
+    }
+
+    pub(super) fn journaled_step(&mut self) -> Result<Option<RunState>, IntcodeError> {
+        let ip = self.ip;
+        let relative_base = self.relative_base;
+        let halted = self.halted;
+        let exit_code = self.exit_code;
+        let queued = self.inputs.len();
+        let next_input = self.inputs.front().cloned();
+
+        // Writes noted by an instruction that failed belong to no entry.
+        self.journal.as_mut().unwrap().pending.clear();
+
+        let result = self.execute()?;
+        let consumed = self.inputs.len() < queued;
+        let journal = self.journal.as_mut().unwrap();
+        let writes = mem::take(&mut journal.pending);
+
+        // Waiting for input changes nothing.
+        if result == Some(RunState::NeedsInput) {
+            return Ok(result);
+        }
+
+        let output = match result {
+            Some(RunState::Output(value)) => Some(value),
+            _ => None,
+        };
+
+        journal.entries.push(Entry {
+            ip,
+            relative_base,
+            halted,
+            exit_code,
+            writes,
+            input: next_input.filter(|_| consumed),
+            output,
+        });
+
+        Ok(result)
+    }
+
+    // Undoes the last instruction. Its input goes back to the front of `inputs`.
+    // False when there is nothing to undo.
+    pub fn step_back(&mut self) -> bool {
+        let entry = match self.journal.as_mut().and_then(|j| j.entries.pop()) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        self.ip = entry.ip;
+        self.relative_base = entry.relative_base;
+        self.halted = entry.halted;
+        self.exit_code = entry.exit_code;
+
+        for (address, previous) in entry.writes.into_iter().rev() {
+            self.memory.write(address, previous);
+        }
+
+        if let Some(input) = entry.input {
+            self.inputs.push_front(input);
+        }
+
+        true
+    }
+
+    // Steps back until `count` instructions have run. False if the journal
+    // doesn't reach that far, in which case the machine is left untouched.
+    pub fn rewind_to(&mut self, count: usize) -> bool {
+        match self.instruction_count() {
+            Some(current) if count <= current => {
+                for _ in count..current {
+                    self.step_back();
+                }
+
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // A copy of the machine as it was after `count` instructions, journal
+    // included, so it can run on from there or be rewound further.
+    #[allow(dead_code)]
+    pub fn branch(&self, count: usize) -> Option<Self> {
+        if self.instruction_count()? < count {
+            return None;
+        }
+
+        let mut machine = self.clone();
+        machine.rewind_to(count);
+        Some(machine)
+    }
+}
@@ -0,0 +1,140 @@
+use super::Machine;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+
+// Program text is a list of integers separated by commas, whitespace or both,
+// so a program can be split across lines. `#` starts a comment that runs to
+// the end of the line.
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "{}", error),
+            LoadError::Syntax {
+                line,
+                column,
+                message,
+            } => write!(f, "Line {}, column {}: {}", line, column, message),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> Self {
+        LoadError::Io(error)
+    }
+}
+
+fn syntax_error<T>(line: usize, column: usize, message: String) -> Result<T, LoadError> {
+    Err(LoadError::Syntax {
+        line,
+        column,
+        message,
+    })
+}
+
+pub fn parse(text: &str) -> Result<Vec<i64>, LoadError> {
+    let mut program = Vec::new();
+
+    // Where the last comma was, if no value has followed it yet.
+    let mut open_comma: Option<(usize, usize)> = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let code = line.split('#').next().unwrap_or("");
+        // Columns count characters, not bytes.
+        let mut chars = code.char_indices().enumerate().peekable();
+
+        while let Some((count, (start, c))) = chars.next() {
+            let column = count + 1;
+
+            if c.is_whitespace() {
+                continue;
+            }
+
+            if c == ',' {
+                if open_comma.is_some() || program.is_empty() {
+                    return syntax_error(line_number, column, "Missing value".to_string());
+                }
+
+                open_comma = Some((line_number, column));
+                continue;
+            }
+
+            let mut end = start + c.len_utf8();
+
+            while let Some((_, (i, c))) = chars.peek() {
+                if *c == ',' || c.is_whitespace() {
+                    break;
+                }
+
+                end = i + c.len_utf8();
+                chars.next();
+            }
+
+            let token = &code[start..end];
+
+            match token.parse() {
+                Ok(value) => program.push(value),
+                Err(_) => {
+                    return syntax_error(line_number, column, format!("Invalid value '{}'", token))
+                }
+            }
+
+            open_comma = None;
+        }
+    }
+
+    if let Some((line, column)) = open_comma {
+        return syntax_error(line, column, "Missing value after comma".to_string());
+    }
+
+    if program.is_empty() {
+        return syntax_error(1, 1, "Empty program".to_string());
+    }
+
+    Ok(program)
+}
+
+pub fn read<R: Read>(mut reader: R) -> Result<Vec<i64>, LoadError> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    parse(&text)
+}
+
+pub fn read_file(path: &str) -> Result<Vec<i64>, LoadError> {
+    read(File::open(path)?)
+}
+
+impl Machine {
+    #[allow(dead_code)]
+    pub fn parse(text: &str) -> Result<Self, LoadError> {
+        Ok(Machine::init(&parse(text)?))
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, LoadError> {
+        Ok(Machine::init(&read(reader)?))
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, LoadError> {
+        Ok(Machine::init(&read_file(path)?))
+    }
+
+    pub fn from_stdin() -> Result<Self, LoadError> {
+        Machine::from_reader(io::stdin())
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::mem::size_of;
+use std::sync::Arc;
+
+// Storage behind a `Machine`. Every cell reads as zero until written. Nothing
+// in it may point into itself, so machines stay `Unpin`.
+pub trait Memory: Clone + Debug + Unpin {
+    fn new(program: &[i64]) -> Self;
+
+    // Length of the loaded program. Anything past it is extended memory.
+    fn program_len(&self) -> usize;
+
+    fn read(&self, address: usize) -> i64;
+
+    fn write(&mut self, address: usize, value: i64);
+
+    // Non-zero cells past the program region, in address order.
+    fn extended(&self) -> Vec<(usize, i64)>;
+
+    // Number of cells `extended` would list, kept up to date on every write.
+    fn extended_len(&self) -> usize;
+
+    // Bytes this memory doesn't share with any clone.
+    fn owned_bytes(&self) -> usize;
+
+    // Current contents of the program region.
+    fn to_vec(&self) -> Vec<i64> {
+        (0..self.program_len()).map(|a| self.read(a)).collect()
+    }
+}
+
+// `count` after a cell past the program goes from `old` to `new`.
+#[inline(always)]
+fn recount(count: usize, old: i64, new: i64) -> usize {
+    count + (new != 0) as usize - (old != 0) as usize
+}
+
+// Memory is split into fixed size pages shared between clones.
+// A write copies only the page it touches, so cloning a machine costs one
+// reference count bump per page instead of copying the whole program.
+// Addresses past `MAX_PAGES` fall back to a hash map so a stray huge address
+// doesn't allocate a giant page table.
+
+const PAGE_BITS: usize = 9;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+const PAGE_MASK: usize = PAGE_SIZE - 1;
+const MAX_PAGES: usize = 1 << 12;
+
+type Page = [i64; PAGE_SIZE];
+
+#[derive(Clone, Debug)]
+pub struct PagedMemory {
+    len: usize,
+    pages: Vec<Arc<Page>>,
+    zero: Arc<Page>,
+    far: HashMap<usize, i64>,
+    extended: usize,
+}
+
+impl Memory for PagedMemory {
+    fn new(program: &[i64]) -> Self {
+        let zero = Arc::new([0; PAGE_SIZE]);
+        let pages = program
+            .chunks(PAGE_SIZE)
+            .map(|chunk| {
+                let mut page = [0; PAGE_SIZE];
+                page[..chunk.len()].copy_from_slice(chunk);
+                Arc::new(page)
+            })
+            .collect();
+
+        Self {
+            len: program.len(),
+            pages,
+            zero,
+            far: HashMap::new(),
+            extended: 0,
+        }
+    }
+
+    fn program_len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    fn read(&self, address: usize) -> i64 {
+        match self.pages.get(address >> PAGE_BITS) {
+            Some(page) => page[address & PAGE_MASK],
+            None if address >> PAGE_BITS < MAX_PAGES => 0,
+            None => *self.far.get(&address).unwrap_or(&0),
+        }
+    }
+
+    #[inline(always)]
+    fn write(&mut self, address: usize, value: i64) {
+        let index = address >> PAGE_BITS;
+
+        if index >= MAX_PAGES {
+            let old = self.far.insert(address, value).unwrap_or(0);
+            self.extended = recount(self.extended, old, value);
+            return;
+        }
+
+        if index >= self.pages.len() {
+            if value == 0 {
+                return;
+            }
+
+            self.pages.resize(index + 1, self.zero.clone());
+        }
+
+        let cell = &mut Arc::make_mut(&mut self.pages[index])[address & PAGE_MASK];
+
+        if address >= self.len {
+            self.extended = recount(self.extended, *cell, value);
+        }
+
+        *cell = value;
+    }
+
+    fn extended(&self) -> Vec<(usize, i64)> {
+        let paged_end = self.pages.len() * PAGE_SIZE;
+        let mut cells: Vec<(usize, i64)> = (self.len..paged_end)
+            .map(|a| (a, self.read(a)))
+            .filter(|(_, v)| *v != 0)
+            .collect();
+        let mut far: Vec<(usize, i64)> = self
+            .far
+            .iter()
+            .filter(|(_, v)| **v != 0)
+            .map(|(a, v)| (*a, *v))
+            .collect();
+        far.sort();
+        cells.extend(far);
+        cells
+    }
+
+    fn extended_len(&self) -> usize {
+        self.extended
+    }
+
+    fn owned_bytes(&self) -> usize {
+        let pages = self
+            .pages
+            .iter()
+            .filter(|p| Arc::strong_count(p) == 1)
+            .count();
+        pages * size_of::<Page>() + self.far.len() * size_of::<(usize, i64)>()
+    }
+}
+
+// The original layout: the program in a Vec and everything past it in a hash map.
+#[derive(Clone, Debug)]
+pub struct FlatMemory {
+    positions: Vec<i64>,
+    extended_memory: HashMap<usize, i64>,
+    extended: usize,
+}
+
+impl Memory for FlatMemory {
+    fn new(program: &[i64]) -> Self {
+        Self {
+            positions: program.to_vec(),
+            extended_memory: HashMap::new(),
+            extended: 0,
+        }
+    }
+
+    fn program_len(&self) -> usize {
+        self.positions.len()
+    }
+
+    #[inline(always)]
+    fn read(&self, offset: usize) -> i64 {
+        if offset < self.positions.len() {
+            self.positions[offset]
+        } else {
+            *self.extended_memory.get(&offset).unwrap_or(&0)
+        }
+    }
+
+    #[inline(always)]
+    fn write(&mut self, offset: usize, value: i64) {
+        if offset < self.positions.len() {
+            self.positions[offset] = value;
+        } else {
+            let old = self.extended_memory.insert(offset, value).unwrap_or(0);
+            self.extended = recount(self.extended, old, value);
+        }
+    }
+
+    fn extended(&self) -> Vec<(usize, i64)> {
+        let mut cells: Vec<(usize, i64)> = self
+            .extended_memory
+            .iter()
+            .filter(|(_, v)| **v != 0)
+            .map(|(a, v)| (*a, *v))
+            .collect();
+        cells.sort();
+        cells
+    }
+
+    fn extended_len(&self) -> usize {
+        self.extended
+    }
+
+    fn owned_bytes(&self) -> usize {
+        self.positions.len() * size_of::<i64>()
+            + self.extended_memory.len() * size_of::<(usize, i64)>()
+    }
+}
+
+// A single Vec that grows to cover the highest address written, zeros
+// included, so reads of a stack past the program stay in bounds. Fastest to
+// read, and cloning a puzzle-sized program outright beats sharing pages.
+// Addresses past `MAX_CELLS` fall back to a hash map, like `PagedMemory`, so
+// a stray huge address doesn't allocate everything below it.
+
+const MAX_CELLS: usize = MAX_PAGES * PAGE_SIZE;
+
+#[derive(Clone, Debug)]
+pub struct DenseMemory {
+    len: usize,
+    cells: Vec<i64>,
+    far: HashMap<usize, i64>,
+    extended: usize,
+}
+
+impl DenseMemory {
+    // Kept out of line so the common case stays a bounds check.
+    #[cold]
+    #[inline(never)]
+    fn read_far(&self, address: usize) -> i64 {
+        *self.far.get(&address).unwrap_or(&0)
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn write_far(&mut self, address: usize, value: i64) {
+        let old = self.far.insert(address, value).unwrap_or(0);
+        self.extended = recount(self.extended, old, value);
+    }
+}
+
+impl Memory for DenseMemory {
+    fn new(program: &[i64]) -> Self {
+        Self {
+            len: program.len(),
+            cells: program.to_vec(),
+            far: HashMap::new(),
+            extended: 0,
+        }
+    }
+
+    fn program_len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    fn read(&self, address: usize) -> i64 {
+        match self.cells.get(address) {
+            Some(value) => *value,
+            None => self.read_far(address),
+        }
+    }
+
+    #[inline(always)]
+    fn write(&mut self, address: usize, value: i64) {
+        if address >= self.cells.len() {
+            if address >= MAX_CELLS {
+                return self.write_far(address, value);
+            }
+
+            self.cells.resize(address + 1, 0);
+        }
+
+        if address >= self.len {
+            self.extended = recount(self.extended, self.cells[address], value);
+        }
+
+        self.cells[address] = value;
+    }
+
+    fn extended(&self) -> Vec<(usize, i64)> {
+        let mut far: Vec<(usize, i64)> = self
+            .far
+            .iter()
+            .filter(|(_, v)| **v != 0)
+            .map(|(a, v)| (*a, *v))
+            .collect();
+        far.sort_unstable();
+
+        (self.len..self.cells.len())
+            .map(|a| (a, self.cells[a]))
+            .filter(|(_, v)| *v != 0)
+            .chain(far)
+            .collect()
+    }
+
+    fn extended_len(&self) -> usize {
+        self.extended
+    }
+
+    fn owned_bytes(&self) -> usize {
+        (self.cells.len() + 2 * self.far.len()) * size_of::<i64>()
+    }
+}
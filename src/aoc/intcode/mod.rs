@@ -0,0 +1,464 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::time::Instant;
+
+pub mod ascii;
+pub mod assembler;
+pub mod batch;
+pub mod cfg;
+pub mod coverage;
+pub mod debugger;
+pub mod decode;
+pub mod decompiler;
+pub mod diff;
+pub mod disassembler;
+pub mod extension;
+pub mod instruction;
+mod interpreter;
+pub mod journal;
+pub mod loader;
+pub mod memory;
+pub mod network;
+pub mod numeric;
+pub mod pipeline;
+pub mod profiler;
+pub mod snapshot;
+pub mod stream;
+
+#[cfg(test)]
+mod tests;
+
+use coverage::Coverage;
+use decode::{DecodeCache, Decoded};
+use extension::Extensions;
+use instruction::Instruction;
+use interpreter::Interpreter;
+use journal::Journal;
+use memory::{DenseMemory, Memory};
+
+#[inline(always)]
+fn mode_arg1(op_code: i64) -> i64 {
+    (op_code % 1000) / 100
+}
+
+#[inline(always)]
+fn mode_arg2(op_code: i64) -> i64 {
+    (op_code % 10_000) / 1000
+}
+
+#[inline(always)]
+fn mode_arg3(op_code: i64) -> i64 {
+    (op_code % 100_000) / 10_000
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunState {
+    Halted,
+    NeedsInput,
+
+    // Only returned by `run_until_output`. `run` collects outputs into `values` instead.
+    Output(i64),
+
+    // A limit ran out before the next instruction. Raise it and run again to resume.
+    Stopped(Limit),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Instructions,
+    Deadline,
+}
+
+// The deadline is only checked every this many instructions. Must be a power of two.
+const DEADLINE_INTERVAL: u64 = 1 << 12;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntcodeError {
+    InvalidOpcode {
+        ip: usize,
+        instruction: i64,
+    },
+    InvalidMode {
+        ip: usize,
+        instruction: i64,
+        mode: i64,
+    },
+    NegativeAddress {
+        ip: usize,
+        instruction: i64,
+        address: i64,
+    },
+    ImmediateWrite {
+        ip: usize,
+        instruction: i64,
+    },
+    InputExhausted {
+        ip: usize,
+        instruction: i64,
+    },
+    LimitReached {
+        ip: usize,
+        instruction: i64,
+        limit: Limit,
+    },
+
+    // A sum, product, relative base or address that doesn't fit in an `i64`.
+    Overflow {
+        ip: usize,
+        instruction: i64,
+    },
+}
+
+impl IntcodeError {
+    pub fn ip(&self) -> usize {
+        match *self {
+            IntcodeError::InvalidOpcode { ip, .. }
+            | IntcodeError::InvalidMode { ip, .. }
+            | IntcodeError::NegativeAddress { ip, .. }
+            | IntcodeError::ImmediateWrite { ip, .. }
+            | IntcodeError::InputExhausted { ip, .. }
+            | IntcodeError::LimitReached { ip, .. }
+            | IntcodeError::Overflow { ip, .. } => ip,
+        }
+    }
+
+    pub fn instruction(&self) -> i64 {
+        match *self {
+            IntcodeError::InvalidOpcode { instruction, .. }
+            | IntcodeError::InvalidMode { instruction, .. }
+            | IntcodeError::NegativeAddress { instruction, .. }
+            | IntcodeError::ImmediateWrite { instruction, .. }
+            | IntcodeError::InputExhausted { instruction, .. }
+            | IntcodeError::LimitReached { instruction, .. }
+            | IntcodeError::Overflow { instruction, .. } => instruction,
+        }
+    }
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntcodeError::InvalidOpcode { .. } => write!(f, "Invalid opcode")?,
+            IntcodeError::InvalidMode { mode, .. } => write!(f, "Invalid parameter mode {}", mode)?,
+            IntcodeError::NegativeAddress { address, .. } => {
+                write!(f, "Negative address {}", address)?
+            }
+            IntcodeError::ImmediateWrite { .. } => write!(f, "Write in immediate mode")?,
+            IntcodeError::InputExhausted { .. } => write!(f, "Input exhausted")?,
+            IntcodeError::LimitReached { limit, .. } => write!(f, "{:?} limit reached", limit)?,
+            IntcodeError::Overflow { .. } => write!(f, "Arithmetic overflow")?,
+        }
+
+        write!(f, " at ip {} ({})", self.ip(), self.instruction())
+    }
+}
+
+impl Error for IntcodeError {}
+
+// One flat Vec of cells unless another backend is chosen with `with_backend`.
+#[derive(Debug)]
+pub struct Machine<M: Memory = DenseMemory> {
+    pub inputs: VecDeque<i64>,
+    pub values: VecDeque<i64>,
+    pub ip: usize,
+    pub halted: bool,
+    pub memory: M,
+    pub relative_base: i64,
+
+    // Instructions left before `Limit::Instructions`, and when `Limit::Deadline` hits.
+    budget: Option<u64>,
+    deadline: Option<Instant>,
+
+    decode_cache: Option<DecodeCache>,
+
+    journal: Option<Journal>,
+    coverage: Option<Coverage>,
+
+    extensions: Option<Extensions>,
+    exit_code: Option<i64>,
+}
+
+impl<M: Memory> Clone for Machine<M> {
+    fn clone(&self) -> Self {
+        Self {
+            inputs: self.inputs.clone(),
+            values: self.values.clone(),
+            ip: self.ip,
+            halted: self.halted,
+            memory: self.memory.clone(),
+            relative_base: self.relative_base,
+            budget: self.budget,
+            deadline: self.deadline,
+            decode_cache: self.decode_cache.clone(),
+            journal: self.journal.clone(),
+            coverage: self.coverage.clone(),
+            extensions: self.extensions.clone(),
+            exit_code: self.exit_code,
+        }
+    }
+}
+
+impl Machine {
+    pub fn init(positions: &[i64]) -> Self {
+        let mut machine = Machine::new(
+            VecDeque::new(),
+            VecDeque::new(),
+            0,
+            false,
+            DenseMemory::new(positions),
+            0,
+        );
+        machine.decode_cache = Some(DecodeCache::new(positions));
+        machine
+    }
+}
+
+impl<M: Memory> Machine<M> {
+    // Without a decode cache. Loading a program builds one, once, and clones
+    // share it.
+    pub fn new(
+        inputs: VecDeque<i64>,
+        values: VecDeque<i64>,
+        ip: usize,
+        halted: bool,
+        memory: M,
+        relative_base: i64,
+    ) -> Self {
+        Self {
+            inputs,
+            values,
+            ip,
+            halted,
+            memory,
+            relative_base,
+            budget: None,
+            deadline: None,
+            decode_cache: None,
+            journal: None,
+            coverage: None,
+            extensions: None,
+            exit_code: None,
+        }
+    }
+
+    // The same machine state on a different memory backend.
+    pub fn with_backend<N: Memory>(&self) -> Machine<N> {
+        let mut memory = N::new(&self.memory.to_vec());
+
+        for (address, value) in self.memory.extended() {
+            memory.write(address, value);
+        }
+
+        let mut machine = Machine::new(
+            self.inputs.clone(),
+            self.values.clone(),
+            self.ip,
+            self.halted,
+            memory,
+            self.relative_base,
+        );
+        machine.decode_cache = self.decode_cache.clone();
+        machine
+    }
+
+    // On for loaded programs. Turning it back on decodes the program as it is now.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = if enabled {
+            Some(DecodeCache::new(&self.memory.to_vec()))
+        } else {
+            None
+        };
+    }
+
+    // `None` removes the limit. A limit carries over into clones.
+    pub fn set_instruction_budget(&mut self, budget: Option<u64>) {
+        self.budget = budget;
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.inputs.push_back(value);
+    }
+
+    // Inputs are consumed in order. Unused inputs stay queued for the next run.
+    // Outputs accumulate in `values` until the caller drains them.
+    pub fn run<I>(&mut self, inputs: I) -> Result<RunState, IntcodeError>
+    where
+        I: IntoIterator<Item = i64>,
+    {
+        self.inputs.extend(inputs);
+
+        loop {
+            match self.run_until_output()? {
+                RunState::Output(value) => self.values.push_back(value),
+                state => return Ok(state),
+            }
+        }
+    }
+
+    // For programs that must finish with the given inputs.
+    pub fn run_to_halt<I>(&mut self, inputs: I) -> Result<(), IntcodeError>
+    where
+        I: IntoIterator<Item = i64>,
+    {
+        match self.run(inputs)? {
+            RunState::NeedsInput => {
+                Err(self.error(|ip, instruction| IntcodeError::InputExhausted { ip, instruction }))
+            }
+            RunState::Stopped(limit) => {
+                Err(self.error(|ip, instruction| IntcodeError::LimitReached {
+                    ip,
+                    instruction,
+                    limit,
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn run_until_output(&mut self) -> Result<RunState, IntcodeError> {
+        if profiler::is_active() {
+            return self.run_until_output_profiled(None);
+        }
+
+        if self.budget.is_none() && self.deadline.is_none() {
+            loop {
+                if let Some(state) = self.step()? {
+                    return Ok(state);
+                }
+            }
+        }
+
+        let mut executed = 0;
+
+        loop {
+            if let Some(state) = self.limited_step(executed)? {
+                return Ok(state);
+            }
+
+            executed += 1;
+        }
+    }
+
+    // `step` under the limits, with `executed` counted from the start of the run.
+    // Waiting for input doesn't count against the budget.
+    pub(super) fn limited_step(&mut self, executed: u64) -> Result<Option<RunState>, IntcodeError> {
+        if let Some(deadline) = self.deadline {
+            if executed & (DEADLINE_INTERVAL - 1) == 0 && Instant::now() >= deadline {
+                return Ok(Some(RunState::Stopped(Limit::Deadline)));
+            }
+        }
+
+        if self.budget == Some(0) {
+            return Ok(Some(RunState::Stopped(Limit::Instructions)));
+        }
+
+        let result = self.step()?;
+
+        if result != Some(RunState::NeedsInput) {
+            if let Some(budget) = self.budget.as_mut() {
+                *budget -= 1;
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub fn step(&mut self) -> Result<Option<RunState>, IntcodeError> {
+        if self.coverage.is_some() {
+            return self.covered_step();
+        }
+
+        if self.journal.is_some() {
+            return self.journaled_step();
+        }
+
+        self.execute()
+    }
+
+    pub fn write(&mut self, offset: usize, value: i64) {
+        self.memory.write(offset, value)
+    }
+
+    pub fn instruction(&self, address: usize) -> Option<Instruction> {
+        let words: Vec<i64> = (0..4)
+            .map(|i| address.checked_add(i).map_or(0, |a| self.read(a)))
+            .collect();
+        let mut instruction = Instruction::decode(&words, 0)?;
+        instruction.address = address;
+        Some(instruction)
+    }
+
+    pub fn read(&self, offset: usize) -> i64 {
+        self.memory.read(offset)
+    }
+}
+
+impl<M: Memory> Interpreter for Machine<M> {
+    type Value = i64;
+
+    fn ip(&self) -> usize {
+        self.ip
+    }
+
+    fn set_ip(&mut self, ip: usize) {
+        self.ip = ip;
+    }
+
+    fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    fn set_relative_base(&mut self, relative_base: i64) {
+        self.relative_base = relative_base;
+    }
+
+    fn get(&self, address: usize) -> i64 {
+        self.memory.read(address)
+    }
+
+    // Unlike `write`, this is an instruction writing, so it goes in the journal.
+    fn set(&mut self, address: usize, value: i64) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.note_write(address, self.memory.read(address));
+        }
+
+        self.memory.write(address, value);
+    }
+
+    fn peek_input(&self) -> Option<i64> {
+        self.inputs.front().cloned()
+    }
+
+    fn consume_input(&mut self) {
+        self.inputs.pop_front();
+    }
+
+    fn output(&mut self, value: i64) -> Option<RunState> {
+        Some(RunState::Output(value))
+    }
+
+    fn halt(&mut self) {
+        self.halted = true;
+    }
+
+    fn decode(&self, word: i64) -> Decoded {
+        match &self.decode_cache {
+            Some(cache) => cache.decode(self.ip, word),
+            None => Decoded::new(word),
+        }
+    }
+
+    fn execute_unknown(&mut self, word: i64) -> Result<Option<RunState>, IntcodeError> {
+        match self.extension(word) {
+            Some(extension) => self.execute_extension(extension.as_ref(), word),
+            None => {
+                Err(self.error(|ip, instruction| IntcodeError::InvalidOpcode { ip, instruction }))
+            }
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use super::memory::{DenseMemory, Memory};
+use super::profiler;
+use super::{IntcodeError, Machine};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+
+// Each machine reads packets as two inputs, x then y, and gets -1 when its
+// inbox is empty. It sends by outputting a destination followed by x and y.
+//
+// A machine is idle once it has been given -1 and then asks for input again
+// without sending anything. When every machine is idle (or halted) and no
+// packets are in flight, the router decides whether to wake the network.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub source: i64,
+    pub destination: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Send(Vec<Packet>),
+    Stop,
+}
+
+pub trait Router {
+    // A packet addressed outside the network.
+    fn route(&mut self, packet: Packet) -> Flow;
+
+    // Every machine is idle. Nothing else can wake the network, so `Continue` stops it.
+    fn idle(&mut self) -> Flow;
+}
+
+// Keeps the last packet sent to its address and forwards it to machine 0
+// whenever the network goes idle. Stops when it sends the same y twice in a row.
+#[derive(Clone, Debug)]
+pub struct Nat {
+    pub address: i64,
+    pub first: Option<Packet>,
+    pub last: Option<Packet>,
+    pub repeated_y: Option<i64>,
+    sent_y: Option<i64>,
+}
+
+impl Nat {
+    pub fn new(address: i64) -> Self {
+        Self {
+            address,
+            first: None,
+            last: None,
+            repeated_y: None,
+            sent_y: None,
+        }
+    }
+}
+
+impl Router for Nat {
+    fn route(&mut self, packet: Packet) -> Flow {
+        if packet.destination == self.address {
+            self.first.get_or_insert(packet);
+            self.last = Some(packet);
+        }
+
+        Flow::Continue
+    }
+
+    fn idle(&mut self) -> Flow {
+        let packet = match self.last {
+            Some(packet) => packet,
+            None => return Flow::Stop,
+        };
+
+        if self.sent_y == Some(packet.y) {
+            self.repeated_y = Some(packet.y);
+            return Flow::Stop;
+        }
+
+        self.sent_y = Some(packet.y);
+
+        Flow::Send(vec![Packet {
+            source: self.address,
+            destination: 0,
+            x: packet.x,
+            y: packet.y,
+        }])
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    // One thread per machine with channel inboxes.
+    Threaded,
+
+    // Machines take turns in address order on the calling thread, so every run
+    // routes the same packets in the same order.
+    #[allow(dead_code)]
+    Deterministic,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NetworkError {
+    pub address: usize,
+    pub error: IntcodeError,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Machine {}: {}", self.address, self.error)
+    }
+}
+
+impl Error for NetworkError {}
+
+enum Event {
+    Packet(Packet),
+    Idle { address: usize, received: usize },
+    Halted(usize),
+    Fault(NetworkError),
+}
+
+pub struct Network<M: Memory = DenseMemory> {
+    machines: Vec<Machine<M>>,
+}
+
+impl<M: Memory + Send> Network<M> {
+    pub fn new(machines: Vec<Machine<M>>) -> Self {
+        Self { machines }
+    }
+
+    // `size` copies of `program`, each given its address as the first input.
+    pub fn boot(program: &Machine<M>, size: usize) -> Self {
+        let machines = (0..size)
+            .map(|address| {
+                let mut machine = program.clone();
+                machine.push_input(address as i64);
+                machine
+            })
+            .collect();
+
+        Network::new(machines)
+    }
+
+    pub fn run<R: Router>(self, router: &mut R, mode: Mode) -> Result<(), NetworkError> {
+        match mode {
+            Mode::Threaded => self.run_threaded(router),
+            Mode::Deterministic => self.run_deterministic(router),
+        }
+    }
+
+    fn run_deterministic<R: Router>(mut self, router: &mut R) -> Result<(), NetworkError> {
+        let size = self.machines.len();
+        let mut inboxes: Vec<VecDeque<Packet>> = vec![VecDeque::new(); size];
+        let mut polled = vec![false; size];
+
+        loop {
+            let mut idle = true;
+
+            for address in 0..size {
+                if self.machines[address].halted {
+                    continue;
+                }
+
+                let sent = flush(address, &mut self.machines[address])?;
+
+                if sent.is_empty() && polled[address] && inboxes[address].is_empty() {
+                    continue;
+                }
+
+                idle = false;
+
+                let machines = &self.machines;
+                let running = dispatch(sent.into(), size, router, |to, packet| {
+                    if !machines[to].halted {
+                        inboxes[to].push_back(packet);
+                    }
+                });
+
+                if !running {
+                    return Ok(());
+                }
+
+                let packet = inboxes[address].pop_front();
+                polled[address] = feed(&mut self.machines[address], packet);
+            }
+
+            if idle {
+                let packets = match router.idle() {
+                    Flow::Send(packets) => packets,
+                    Flow::Continue | Flow::Stop => return Ok(()),
+                };
+
+                let machines = &self.machines;
+                let running = dispatch(packets.into(), size, router, |to, packet| {
+                    if !machines[to].halted {
+                        inboxes[to].push_back(packet);
+                    }
+                });
+
+                if !running {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn run_threaded<R: Router>(self, router: &mut R) -> Result<(), NetworkError> {
+        let size = self.machines.len();
+        let profiling = profiler::is_active();
+
+        thread::scope(|scope| {
+            let (events, event_queue) = channel();
+            let mut inboxes = Vec::new();
+            let mut workers = Vec::new();
+
+            for (address, machine) in self.machines.into_iter().enumerate() {
+                let (inbox, packets) = channel();
+                let events = events.clone();
+                inboxes.push(inbox);
+                workers.push(scope.spawn(move || {
+                    if profiling {
+                        profiler::start();
+                    }
+
+                    worker(address, machine, packets, events);
+                    profiler::finish()
+                }));
+            }
+
+            drop(events);
+
+            let result = coordinate(size, &inboxes, &event_queue, router);
+
+            // Closing the inboxes and the event queue stops the workers.
+            drop(inboxes);
+            drop(event_queue);
+
+            for profile in workers.into_iter().filter_map(|w| w.join().unwrap()) {
+                profiler::with_active(|active| active.merge(&profile));
+            }
+
+            result
+        })
+    }
+}
+
+// Runs until the machine wants input and returns the packets it sent.
+fn flush<M: Memory>(address: usize, machine: &mut Machine<M>) -> Result<Vec<Packet>, NetworkError> {
+    if let Err(error) = machine.run(vec![]) {
+        return Err(NetworkError { address, error });
+    }
+
+    let mut values: Vec<i64> = machine.values.drain(..).collect();
+    let complete = values.len() - values.len() % 3;
+
+    // A packet still being written is kept for the next flush.
+    machine.values.extend(values.drain(complete..));
+
+    Ok(values
+        .chunks(3)
+        .map(|p| Packet {
+            source: address as i64,
+            destination: p[0],
+            x: p[1],
+            y: p[2],
+        })
+        .collect())
+}
+
+// Gives the machine its next packet, or -1. Returns whether it got -1.
+fn feed<M: Memory>(machine: &mut Machine<M>, packet: Option<Packet>) -> bool {
+    match packet {
+        Some(packet) => {
+            machine.inputs.extend([packet.x, packet.y]);
+            false
+        }
+        None => {
+            machine.push_input(-1);
+            true
+        }
+    }
+}
+
+// Hands packets for machines to `send` and everything else to the router.
+// Returns false if the router stopped the network.
+fn dispatch<R, F>(mut pending: VecDeque<Packet>, size: usize, router: &mut R, mut send: F) -> bool
+where
+    R: Router,
+    F: FnMut(usize, Packet),
+{
+    while let Some(packet) = pending.pop_front() {
+        let address = usize::try_from(packet.destination)
+            .ok()
+            .filter(|a| *a < size);
+
+        match address {
+            Some(address) => send(address, packet),
+            None => match router.route(packet) {
+                Flow::Continue => {}
+                Flow::Send(packets) => pending.extend(packets),
+                Flow::Stop => return false,
+            },
+        }
+    }
+
+    true
+}
+
+fn worker<M: Memory>(
+    address: usize,
+    mut machine: Machine<M>,
+    packets: Receiver<Packet>,
+    events: Sender<Event>,
+) {
+    let mut received = 0;
+    let mut polled = false;
+
+    loop {
+        let sent = match flush(address, &mut machine) {
+            Ok(sent) => sent,
+            Err(error) => {
+                let _ = events.send(Event::Fault(error));
+                return;
+            }
+        };
+        let sent_any = !sent.is_empty();
+
+        for packet in sent {
+            if events.send(Event::Packet(packet)).is_err() {
+                return;
+            }
+        }
+
+        if machine.halted {
+            let _ = events.send(Event::Halted(address));
+            return;
+        }
+
+        let packet = match packets.try_recv() {
+            Ok(packet) => Some(packet),
+            Err(TryRecvError::Disconnected) => return,
+            Err(TryRecvError::Empty) if polled && !sent_any => {
+                if events.send(Event::Idle { address, received }).is_err() {
+                    return;
+                }
+
+                match packets.recv() {
+                    Ok(packet) => Some(packet),
+                    Err(_) => return,
+                }
+            }
+            Err(TryRecvError::Empty) => None,
+        };
+
+        if packet.is_some() {
+            received += 1;
+        }
+
+        polled = feed(&mut machine, packet);
+    }
+}
+
+fn coordinate<R: Router>(
+    size: usize,
+    inboxes: &[Sender<Packet>],
+    events: &Receiver<Event>,
+    router: &mut R,
+) -> Result<(), NetworkError> {
+    let mut delivered = vec![0; size];
+    let mut idle = vec![false; size];
+    let mut halted = vec![false; size];
+
+    loop {
+        let mut pending = VecDeque::new();
+        let event = match events.recv() {
+            Ok(event) => event,
+            Err(_) => return Ok(()),
+        };
+
+        match event {
+            Event::Packet(packet) => pending.push_back(packet),
+
+            // A machine that reports idle before a packet reached it is not idle.
+            Event::Idle { address, received } => {
+                idle[address] = received == delivered[address];
+            }
+
+            Event::Halted(address) => {
+                halted[address] = true;
+                idle[address] = true;
+            }
+
+            Event::Fault(error) => return Err(error),
+        }
+
+        if pending.is_empty() && idle.iter().all(|i| *i) {
+            match router.idle() {
+                Flow::Send(packets) => pending.extend(packets),
+                Flow::Continue | Flow::Stop => return Ok(()),
+            }
+        }
+
+        let running = dispatch(pending, size, router, |to, packet| {
+            if !halted[to] {
+                delivered[to] += 1;
+                idle[to] = false;
+                let _ = inboxes[to].send(packet);
+            }
+        });
+
+        if !running {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use super::interpreter::Interpreter;
+use super::memory::Memory;
+use super::{IntcodeError, Machine, RunState};
+use num::{CheckedAdd, CheckedMul, ToPrimitive, Zero};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+// A machine with a choice of number type, for programs that outgrow `i64`.
+// It runs the same instruction set as `Machine`, so `i64` and `i128` report
+// overflow as an error with the ip of the instruction, and `num::BigInt`
+// never overflows. Addresses, the relative base and instruction words must
+// still fit in an `i64`.
+//
+// It is a bare interpreter: memory is its own Vec and hash map, and there
+// are no limits, extensions, journal or coverage. The memory backends store
+// `i64` cells, and those features record and hand out `i64` values, so they
+// would all need a second copy for each number type. Run a program here to
+// check it or to get past an overflow, and use `Machine` for everything else.
+
+pub trait Number: Clone + fmt::Debug + PartialEq + PartialOrd + From<i64> {
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn to_i64(&self) -> Option<i64>;
+    fn is_zero(&self) -> bool;
+}
+
+impl<T> Number for T
+where
+    T: Clone + fmt::Debug + PartialEq + PartialOrd + From<i64>,
+    T: CheckedAdd + CheckedMul + ToPrimitive + Zero,
+{
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        CheckedAdd::checked_add(self, other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        CheckedMul::checked_mul(self, other)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct NumericMachine<N: Number> {
+    pub inputs: VecDeque<N>,
+    pub values: VecDeque<N>,
+    pub ip: usize,
+    pub halted: bool,
+    pub relative_base: i64,
+    positions: Vec<N>,
+    extended_memory: HashMap<usize, N>,
+}
+
+impl<M: Memory> Machine<M> {
+    // The same machine state with another number type.
+    #[allow(dead_code)]
+    pub fn with_numbers<N: Number>(&self) -> NumericMachine<N> {
+        let mut machine = NumericMachine::new(&self.memory.to_vec());
+
+        for (address, value) in self.memory.extended() {
+            machine.write(address, N::from(value));
+        }
+
+        machine.inputs = self.inputs.iter().map(|v| N::from(*v)).collect();
+        machine.values = self.values.iter().map(|v| N::from(*v)).collect();
+        machine.ip = self.ip;
+        machine.halted = self.halted;
+        machine.relative_base = self.relative_base;
+        machine
+    }
+}
+
+#[allow(dead_code)]
+impl<N: Number> NumericMachine<N> {
+    pub fn new(program: &[i64]) -> Self {
+        Self {
+            inputs: VecDeque::new(),
+            values: VecDeque::new(),
+            ip: 0,
+            halted: false,
+            relative_base: 0,
+            positions: program.iter().map(|v| N::from(*v)).collect(),
+            extended_memory: HashMap::new(),
+        }
+    }
+
+    pub fn read(&self, address: usize) -> N {
+        match self.positions.get(address) {
+            Some(value) => value.clone(),
+            None => self
+                .extended_memory
+                .get(&address)
+                .cloned()
+                .unwrap_or_else(|| N::from(0)),
+        }
+    }
+
+    pub fn write(&mut self, address: usize, value: N) {
+        match self.positions.get_mut(address) {
+            Some(cell) => *cell = value,
+            None => {
+                self.extended_memory.insert(address, value);
+            }
+        }
+    }
+
+    // Outputs go to `values`, so this only stops to halt or wait for input.
+    pub fn run<I>(&mut self, inputs: I) -> Result<RunState, IntcodeError>
+    where
+        I: IntoIterator<Item = N>,
+    {
+        self.inputs.extend(inputs);
+
+        loop {
+            if let Some(state) = self.execute()? {
+                return Ok(state);
+            }
+        }
+    }
+
+    pub fn run_to_halt<I>(&mut self, inputs: I) -> Result<(), IntcodeError>
+    where
+        I: IntoIterator<Item = N>,
+    {
+        match self.run(inputs)? {
+            RunState::NeedsInput => {
+                Err(self.error(|ip, instruction| IntcodeError::InputExhausted { ip, instruction }))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<N: Number> Interpreter for NumericMachine<N> {
+    type Value = N;
+
+    fn ip(&self) -> usize {
+        self.ip
+    }
+
+    fn set_ip(&mut self, ip: usize) {
+        self.ip = ip;
+    }
+
+    fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    fn set_relative_base(&mut self, relative_base: i64) {
+        self.relative_base = relative_base;
+    }
+
+    fn get(&self, address: usize) -> N {
+        self.read(address)
+    }
+
+    fn set(&mut self, address: usize, value: N) {
+        self.write(address, value);
+    }
+
+    fn peek_input(&self) -> Option<N> {
+        self.inputs.front().cloned()
+    }
+
+    fn consume_input(&mut self) {
+        self.inputs.pop_front();
+    }
+
+    // Outputs go to `values`, since `RunState` only carries an `i64`.
+    fn output(&mut self, value: N) -> Option<RunState> {
+        self.values.push_back(value);
+        None
+    }
+
+    fn halt(&mut self) {
+        self.halted = true;
+    }
+}
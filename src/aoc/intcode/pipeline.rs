@@ -0,0 +1,167 @@
+use super::memory::{DenseMemory, Memory};
+use super::{IntcodeError, Machine};
+use std::error::Error;
+use std::fmt;
+
+// Machines wired output to input. Each stage's outputs are copied to every
+// stage it connects to, and a stage with several sources reads their values
+// in the order they were produced. Stages take turns running until they all
+// halt or none of them can make progress.
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Trace {
+    pub inputs: Vec<i64>,
+    pub outputs: Vec<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PipelineRun {
+    // Last value written by the exit stage.
+    pub output: Option<i64>,
+    pub halted: bool,
+    pub traces: Vec<Trace>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PipelineError {
+    pub stage: usize,
+    pub error: IntcodeError,
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Stage {}: {}", self.stage, self.error)
+    }
+}
+
+impl Error for PipelineError {}
+
+#[derive(Clone, Debug)]
+pub struct Pipeline<M: Memory = DenseMemory> {
+    stages: Vec<Machine<M>>,
+    targets: Vec<Vec<usize>>,
+    traces: Vec<Trace>,
+    exit: usize,
+}
+
+impl<M: Memory> Default for Pipeline<M> {
+    fn default() -> Self {
+        Self {
+            stages: Vec::new(),
+            targets: Vec::new(),
+            traces: Vec::new(),
+            exit: 0,
+        }
+    }
+}
+
+impl<M: Memory> Pipeline<M> {
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    // One copy of `program` per phase, each fed into the next.
+    pub fn chain(program: &Machine<M>, phases: &[i64]) -> Self {
+        let mut pipeline = Pipeline::new();
+
+        for (stage, phase) in phases.iter().enumerate() {
+            pipeline.add_stage(program.clone());
+            pipeline.send(stage, *phase);
+
+            if stage > 0 {
+                pipeline.connect(stage - 1, stage);
+            }
+        }
+
+        pipeline
+    }
+
+    // A chain whose last stage also feeds the first.
+    pub fn feedback_loop(program: &Machine<M>, phases: &[i64]) -> Self {
+        let mut pipeline = Pipeline::chain(program, phases);
+
+        if !phases.is_empty() {
+            pipeline.connect(phases.len() - 1, 0);
+        }
+
+        pipeline
+    }
+
+    // The newest stage becomes the exit until `set_exit` says otherwise.
+    pub fn add_stage(&mut self, machine: Machine<M>) -> usize {
+        self.stages.push(machine);
+        self.targets.push(Vec::new());
+        self.traces.push(Trace::default());
+        self.exit = self.stages.len() - 1;
+        self.exit
+    }
+
+    pub fn connect(&mut self, from: usize, to: usize) {
+        self.targets[from].push(to);
+    }
+
+    #[allow(dead_code)]
+    pub fn set_exit(&mut self, stage: usize) {
+        self.exit = stage;
+    }
+
+    pub fn send(&mut self, stage: usize, value: i64) {
+        self.stages[stage].push_input(value);
+        self.traces[stage].inputs.push(value);
+    }
+
+    // Feeds `inputs` to the first stage and runs every stage to a standstill.
+    pub fn run<I>(mut self, inputs: I) -> Result<PipelineRun, PipelineError>
+    where
+        I: IntoIterator<Item = i64>,
+    {
+        for value in inputs {
+            self.send(0, value);
+        }
+
+        let mut output = None;
+
+        loop {
+            let mut progressed = false;
+
+            for stage in 0..self.stages.len() {
+                let machine = &mut self.stages[stage];
+
+                if machine.halted {
+                    continue;
+                }
+
+                let queued = machine.inputs.len();
+
+                if let Err(error) = machine.run(vec![]) {
+                    return Err(PipelineError { stage, error });
+                }
+
+                let values: Vec<i64> = machine.values.drain(..).collect();
+                progressed |= machine.halted || machine.inputs.len() < queued || !values.is_empty();
+
+                if stage == self.exit {
+                    output = values.last().cloned().or(output);
+                }
+
+                for value in values.iter() {
+                    for target in self.targets[stage].clone() {
+                        self.send(target, *value);
+                    }
+                }
+
+                self.traces[stage].outputs.extend(values);
+            }
+
+            if !progressed {
+                break;
+            }
+        }
+
+        Ok(PipelineRun {
+            output,
+            halted: self.stages.iter().all(|m| m.halted),
+            traces: self.traces,
+        })
+    }
+}
@@ -0,0 +1,246 @@
+use super::instruction::{Opcode, OPCODES};
+use super::memory::Memory;
+use super::{IntcodeError, Machine, RunState};
+use std::cell::RefCell;
+use std::cmp::{max, Reverse};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const TOP_COUNT: usize = 10;
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Profile>> = const { RefCell::new(None) };
+}
+
+// While active, every `Machine::run` on this thread is recorded into a shared profile.
+pub fn start() {
+    ACTIVE.with(|active| *active.borrow_mut() = Some(Profile::default()));
+}
+
+pub fn finish() -> Option<Profile> {
+    ACTIVE.with(|active| active.borrow_mut().take())
+}
+
+pub fn is_active() -> bool {
+    ACTIVE.with(|active| active.borrow().is_some())
+}
+
+pub(super) fn with_active<F, T>(f: F) -> Option<T>
+where
+    F: FnOnce(&mut Profile) -> T,
+{
+    ACTIVE.with(|active| active.borrow_mut().as_mut().map(f))
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub instructions: u64,
+    pub by_opcode: HashMap<Opcode, u64>,
+    pub by_address: HashMap<usize, u64>,
+
+    // Backward jumps, keyed by (target, source).
+    pub loops: HashMap<(usize, usize), u64>,
+
+    pub peak_positions: usize,
+    pub peak_extended: usize,
+    pub peak_owned_bytes: usize,
+}
+
+impl Profile {
+    fn record(&mut self, ip: usize, instruction: i64, next_ip: usize) {
+        self.instructions += 1;
+        *self.by_address.entry(ip).or_insert(0) += 1;
+
+        let opcode = match Opcode::from_instruction(instruction) {
+            Some(opcode) => opcode,
+            None => return,
+        };
+
+        *self.by_opcode.entry(opcode).or_insert(0) += 1;
+
+        let is_jump = opcode == Opcode::JumpIfTrue || opcode == Opcode::JumpIfFalse;
+
+        if is_jump && next_ip <= ip {
+            *self.loops.entry((next_ip, ip)).or_insert(0) += 1;
+        }
+    }
+
+    // Combines profiles of machines that ran at the same time, so memory adds up.
+    pub(super) fn merge(&mut self, other: &Profile) {
+        self.instructions += other.instructions;
+
+        for (opcode, count) in &other.by_opcode {
+            *self.by_opcode.entry(*opcode).or_insert(0) += count;
+        }
+
+        for (address, count) in &other.by_address {
+            *self.by_address.entry(*address).or_insert(0) += count;
+        }
+
+        for (key, count) in &other.loops {
+            *self.loops.entry(*key).or_insert(0) += count;
+        }
+
+        self.peak_positions = max(self.peak_positions, other.peak_positions);
+        self.peak_extended += other.peak_extended;
+        self.peak_owned_bytes += other.peak_owned_bytes;
+    }
+
+    fn record_memory<M: Memory>(&mut self, machine: &Machine<M>) {
+        let memory = &machine.memory;
+        self.peak_positions = max(self.peak_positions, memory.program_len());
+        self.peak_extended = max(self.peak_extended, memory.extended_len());
+        self.peak_owned_bytes = max(self.peak_owned_bytes, memory.owned_bytes());
+    }
+
+    pub fn hot_addresses(&self) -> Vec<(usize, u64)> {
+        let mut addresses: Vec<(usize, u64)> =
+            self.by_address.iter().map(|(a, c)| (*a, *c)).collect();
+        addresses.sort_by_key(|(a, c)| (Reverse(*c), *a));
+        addresses.truncate(TOP_COUNT);
+        addresses
+    }
+
+    pub fn hot_loops(&self) -> Vec<((usize, usize), u64)> {
+        let mut loops: Vec<((usize, usize), u64)> =
+            self.loops.iter().map(|(l, c)| (*l, *c)).collect();
+        loops.sort_by_key(|(l, c)| (Reverse(*c), *l));
+        loops.truncate(TOP_COUNT);
+        loops
+    }
+
+    pub fn report(&self) -> String {
+        let mut text = String::new();
+
+        writeln!(text, "Instructions: {}", self.instructions).unwrap();
+
+        for opcode in OPCODES.iter() {
+            if let Some(count) = self.by_opcode.get(opcode) {
+                writeln!(text, "  {:<4}{:>12}", opcode.mnemonic(), count).unwrap();
+            }
+        }
+
+        writeln!(text, "Hot addresses:").unwrap();
+
+        for (address, count) in self.hot_addresses() {
+            writeln!(text, "  {:04}{:>12}", address, count).unwrap();
+        }
+
+        writeln!(text, "Hot loops:").unwrap();
+
+        for ((start, end), count) in self.hot_loops() {
+            writeln!(text, "  {:04}..{:04}{:>12}", start, end, count).unwrap();
+        }
+
+        writeln!(
+            text,
+            "Memory: {} positions, {} extended, {} bytes not shared with clones",
+            self.peak_positions, self.peak_extended, self.peak_owned_bytes
+        )
+        .unwrap();
+
+        text
+    }
+
+    pub fn to_json(&self) -> String {
+        let opcodes: Vec<String> = OPCODES
+            .iter()
+            .filter_map(|op| {
+                self.by_opcode
+                    .get(op)
+                    .map(|c| format!("\"{}\":{}", op.mnemonic(), c))
+            })
+            .collect();
+
+        let mut addresses: Vec<(&usize, &u64)> = self.by_address.iter().collect();
+        addresses.sort();
+        let addresses: Vec<String> = addresses
+            .iter()
+            .map(|(a, c)| format!("\"{}\":{}", a, c))
+            .collect();
+
+        let loops: Vec<String> = self
+            .hot_loops()
+            .iter()
+            .map(|((start, end), count)| {
+                format!(
+                    "{{\"start\":{},\"end\":{},\"count\":{}}}",
+                    start, end, count
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"instructions\":{},\"opcodes\":{{{}}},\"addresses\":{{{}}},\"hot_loops\":[{}],\"memory\":{{\"positions\":{},\"extended\":{},\"bytes\":{}}}}}",
+            self.instructions,
+            opcodes.join(","),
+            addresses.join(","),
+            loops.join(","),
+            self.peak_positions,
+            self.peak_extended,
+            self.peak_owned_bytes
+        )
+    }
+}
+
+impl<M: Memory> Machine<M> {
+    #[allow(dead_code)]
+    pub fn run_profiled<I>(
+        &mut self,
+        inputs: I,
+        profile: &mut Profile,
+    ) -> Result<RunState, IntcodeError>
+    where
+        I: IntoIterator<Item = i64>,
+    {
+        self.inputs.extend(inputs);
+
+        loop {
+            match self.run_until_output_profiled(Some(profile))? {
+                RunState::Output(value) => self.values.push_back(value),
+                state => return Ok(state),
+            }
+        }
+    }
+
+    // Without a profile, each event goes to the active one, borrowed only while
+    // it's recorded so that machines run by an extension can record too.
+    pub(super) fn run_until_output_profiled(
+        &mut self,
+        mut profile: Option<&mut Profile>,
+    ) -> Result<RunState, IntcodeError> {
+        let mut executed = 0;
+
+        loop {
+            let ip = self.ip;
+            let instruction = self.read(ip);
+            let result = self.limited_step(executed)?;
+            executed += 1;
+
+            match result {
+                Some(RunState::NeedsInput) | Some(RunState::Stopped(_)) => {}
+                _ => {
+                    let next_ip = self.ip;
+                    record(&mut profile, |p| p.record(ip, instruction, next_ip));
+                }
+            }
+
+            if let Some(state) = result {
+                record(&mut profile, |p| p.record_memory(self));
+                return Ok(state);
+            }
+        }
+    }
+}
+
+fn record<F>(profile: &mut Option<&mut Profile>, f: F)
+where
+    F: FnOnce(&mut Profile),
+{
+    match profile {
+        Some(profile) => f(profile),
+        None => {
+            with_active(f);
+        }
+    }
+}
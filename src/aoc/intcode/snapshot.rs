@@ -0,0 +1,356 @@
+use super::decode::DecodeCache;
+use super::memory::Memory;
+use super::Machine;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Two encodings of the same state. Both start with a version so old files stay readable.
+//
+// Text:
+//   intcode-snapshot 2
+//   ip 25
+//   relative_base 1000
+//   halted true
+//   exit_code 3
+//   inputs 1,2
+//   outputs
+//   positions 109,1,204,-1,...
+//   extended 1000=5,1003=-2
+//
+// Binary: the magic bytes, a little endian u32 version, then the same fields in
+// the same order. Sequences are a u64 length followed by little endian i64 values.
+// The exit code is a byte saying whether there is one, then the code if so.
+//
+// Version 1 had no exit code. Its files still load, without one.
+
+pub const VERSION: u32 = 2;
+const TEXT_MAGIC: &str = "intcode-snapshot";
+const BINARY_MAGIC: &[u8; 4] = b"ICSN";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Binary,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    UnknownFormat,
+    UnsupportedVersion(u32),
+    Malformed(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "{}", error),
+            SnapshotError::UnknownFormat => write!(f, "Not an Intcode snapshot"),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "Unsupported snapshot version {}", v),
+            SnapshotError::Malformed(message) => write!(f, "Malformed snapshot: {}", message),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+fn malformed<T>(message: &str) -> Result<T, SnapshotError> {
+    Err(SnapshotError::Malformed(message.to_string()))
+}
+
+fn join<'a, I>(values: I) -> String
+where
+    I: IntoIterator<Item = &'a i64>,
+{
+    let values: Vec<String> = values.into_iter().map(|v| v.to_string()).collect();
+    values.join(",")
+}
+
+fn parse_list(text: &str) -> Result<Vec<i64>, SnapshotError> {
+    text.split(',')
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            v.parse()
+                .map_err(|_| SnapshotError::Malformed(format!("Invalid value {}", v)))
+        })
+        .collect()
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() < count {
+            return malformed("Unexpected end of data");
+        }
+
+        let (head, tail) = self.bytes.split_at(count);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let mut buffer = [0; 8];
+        buffer.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buffer))
+    }
+
+    fn i64(&mut self) -> Result<i64, SnapshotError> {
+        Ok(self.u64()? as i64)
+    }
+
+    fn list(&mut self) -> Result<Vec<i64>, SnapshotError> {
+        let count = self.u64()? as usize;
+
+        if count > self.bytes.len() / 8 {
+            return malformed("Length exceeds data");
+        }
+
+        (0..count).map(|_| self.i64()).collect()
+    }
+}
+
+fn push_list<'a, I>(bytes: &mut Vec<u8>, values: I)
+where
+    I: ExactSizeIterator<Item = &'a i64>,
+{
+    bytes.extend(&(values.len() as u64).to_le_bytes());
+
+    for value in values {
+        bytes.extend(&value.to_le_bytes());
+    }
+}
+
+impl<M: Memory> Machine<M> {
+    pub fn to_text(&self) -> String {
+        let extended: Vec<String> = self
+            .memory
+            .extended()
+            .iter()
+            .map(|(a, v)| format!("{}={}", a, v))
+            .collect();
+
+        format!(
+            "{} {}\nip {}\nrelative_base {}\nhalted {}\nexit_code {}\ninputs {}\noutputs {}\npositions {}\nextended {}\n",
+            TEXT_MAGIC,
+            VERSION,
+            self.ip,
+            self.relative_base,
+            self.halted,
+            join(&self.exit_code),
+            join(&self.inputs),
+            join(&self.values),
+            join(&self.memory.to_vec()),
+            extended.join(",")
+        )
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, SnapshotError> {
+        let mut lines = text.lines();
+        let mut field = |name: &str| -> Result<&str, SnapshotError> {
+            let line = lines.next().unwrap_or("");
+
+            match line.split_once(' ') {
+                Some((key, value)) if key == name => Ok(value.trim()),
+                None if line == name => Ok(""),
+                _ => malformed(&format!("Expected {}", name)),
+            }
+        };
+
+        let version = field(TEXT_MAGIC).map_err(|_| SnapshotError::UnknownFormat)?;
+
+        let version = match version.parse::<u32>() {
+            Ok(v) if v == 1 || v == VERSION => v,
+            Ok(v) => return Err(SnapshotError::UnsupportedVersion(v)),
+            Err(_) => return malformed("Invalid version"),
+        };
+
+        let ip = field("ip")?.parse().or_else(|_| malformed("Invalid ip"))?;
+        let relative_base = field("relative_base")?
+            .parse()
+            .or_else(|_| malformed("Invalid relative_base"))?;
+        let halted = field("halted")?
+            .parse()
+            .or_else(|_| malformed("Invalid halted"))?;
+        let exit_code = if version == 1 {
+            None
+        } else {
+            match parse_list(field("exit_code")?)?.as_slice() {
+                [] => None,
+                [code] => Some(*code),
+                _ => return malformed("Invalid exit_code"),
+            }
+        };
+        let inputs = parse_list(field("inputs")?)?;
+        let outputs = parse_list(field("outputs")?)?;
+        let positions = parse_list(field("positions")?)?;
+        let mut extended = Vec::new();
+
+        for cell in field("extended")?.split(',').filter(|c| !c.is_empty()) {
+            let parsed = cell
+                .split_once('=')
+                .and_then(|(a, v)| Some((a.parse().ok()?, v.parse().ok()?)));
+
+            match parsed {
+                Some(cell) => extended.push(cell),
+                None => return malformed(&format!("Invalid cell {}", cell)),
+            }
+        }
+
+        let mut machine = Machine::restore(
+            ip,
+            relative_base,
+            halted,
+            inputs,
+            outputs,
+            &positions,
+            &extended,
+        );
+        machine.exit_code = exit_code;
+        Ok(machine)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(BINARY_MAGIC);
+        bytes.extend(&VERSION.to_le_bytes());
+        bytes.extend(&(self.ip as u64).to_le_bytes());
+        bytes.extend(&self.relative_base.to_le_bytes());
+        bytes.push(self.halted as u8);
+        bytes.push(self.exit_code.is_some() as u8);
+        bytes.extend(self.exit_code.iter().flat_map(|code| code.to_le_bytes()));
+        push_list(&mut bytes, self.inputs.iter());
+        push_list(&mut bytes, self.values.iter());
+        push_list(&mut bytes, self.memory.to_vec().iter());
+
+        let extended = self.memory.extended();
+        bytes.extend(&(extended.len() as u64).to_le_bytes());
+
+        for (address, value) in extended {
+            bytes.extend(&(address as u64).to_le_bytes());
+            bytes.extend(&value.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut reader = Reader { bytes };
+
+        if reader.take(4).ok() != Some(&BINARY_MAGIC[..]) {
+            return Err(SnapshotError::UnknownFormat);
+        }
+
+        let mut version = [0; 4];
+        version.copy_from_slice(reader.take(4)?);
+        let version = u32::from_le_bytes(version);
+
+        if version != 1 && version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let ip = reader.u64()? as usize;
+        let relative_base = reader.i64()?;
+        let halted = match reader.take(1)?[0] {
+            0 => false,
+            1 => true,
+            _ => return malformed("Invalid halted"),
+        };
+        let exit_code = if version == 1 {
+            None
+        } else {
+            match reader.take(1)?[0] {
+                0 => None,
+                1 => Some(reader.i64()?),
+                _ => return malformed("Invalid exit_code"),
+            }
+        };
+        let inputs = reader.list()?;
+        let outputs = reader.list()?;
+        let positions = reader.list()?;
+        let count = reader.u64()? as usize;
+
+        if count > reader.bytes.len() / 16 {
+            return malformed("Length exceeds data");
+        }
+
+        let extended = (0..count)
+            .map(|_| Ok((reader.u64()? as usize, reader.i64()?)))
+            .collect::<Result<Vec<_>, SnapshotError>>()?;
+
+        if !reader.bytes.is_empty() {
+            return malformed("Trailing data");
+        }
+
+        let mut machine = Machine::restore(
+            ip,
+            relative_base,
+            halted,
+            inputs,
+            outputs,
+            &positions,
+            &extended,
+        );
+        machine.exit_code = exit_code;
+        Ok(machine)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: Format) -> Result<(), SnapshotError> {
+        match format {
+            Format::Text => fs::write(path, self.to_text())?,
+            Format::Binary => fs::write(path, self.to_bytes())?,
+        }
+
+        Ok(())
+    }
+
+    // Either format is accepted.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        let bytes = fs::read(path)?;
+
+        if bytes.starts_with(BINARY_MAGIC) {
+            Machine::from_bytes(&bytes)
+        } else {
+            let text = String::from_utf8(bytes).map_err(|_| SnapshotError::UnknownFormat)?;
+            Machine::from_text(&text)
+        }
+    }
+
+    fn restore(
+        ip: usize,
+        relative_base: i64,
+        halted: bool,
+        inputs: Vec<i64>,
+        outputs: Vec<i64>,
+        positions: &[i64],
+        extended: &[(usize, i64)],
+    ) -> Self {
+        let mut memory = M::new(positions);
+
+        for (address, value) in extended {
+            memory.write(*address, *value);
+        }
+
+        let mut machine = Machine::new(
+            inputs.into_iter().collect(),
+            outputs.into_iter().collect(),
+            ip,
+            halted,
+            memory,
+            relative_base,
+        );
+        machine.decode_cache = Some(DecodeCache::new(positions));
+        machine
+    }
+}
@@ -0,0 +1,120 @@
+use super::interpreter::Interpreter;
+use super::memory::{DenseMemory, Memory};
+use super::{IntcodeError, Machine, RunState};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::stream::{FusedStream, Stream};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// A machine as a stream of its outputs, fed from another stream. Polling runs
+// the machine until it outputs, and when it wants input the input stream is
+// polled, so the machine waits with everything else on the executor. Streams
+// from different machines chain like any other streams.
+//
+// The stream ends when the machine halts. It also ends on an error, when the
+// input runs dry, or when a limit stops the machine. `error` says which.
+
+pub struct MachineStream<S, M: Memory = DenseMemory> {
+    machine: Machine<M>,
+    input: S,
+    error: Option<IntcodeError>,
+    done: bool,
+}
+
+impl<M: Memory> MachineStream<UnboundedReceiver<i64>, M> {
+    // Input comes from the returned sender.
+    pub fn channel(machine: Machine<M>) -> (UnboundedSender<i64>, Self) {
+        let (sender, receiver) = unbounded();
+        (sender, MachineStream::new(machine, receiver))
+    }
+}
+
+impl<S, M> MachineStream<S, M>
+where
+    S: Stream<Item = i64> + Unpin,
+    M: Memory,
+{
+    pub fn new(machine: Machine<M>, input: S) -> Self {
+        Self {
+            machine,
+            input,
+            error: None,
+            done: false,
+        }
+    }
+
+    // Why the stream ended, if not by halting.
+    pub fn error(&self) -> Option<IntcodeError> {
+        self.error
+    }
+
+    #[allow(dead_code)]
+    pub fn into_machine(self) -> Machine<M> {
+        self.machine
+    }
+
+    fn finish(&mut self, error: Option<IntcodeError>) -> Poll<Option<i64>> {
+        self.error = error;
+        self.done = true;
+        Poll::Ready(None)
+    }
+}
+
+impl<S, M> Stream for MachineStream<S, M>
+where
+    S: Stream<Item = i64> + Unpin,
+    M: Memory,
+{
+    type Item = i64;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<i64>> {
+        let this = self.get_mut();
+
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            if let Some(value) = this.machine.values.pop_front() {
+                return Poll::Ready(Some(value));
+            }
+
+            let machine = &mut this.machine;
+
+            match machine.run_until_output() {
+                Ok(RunState::Output(value)) => return Poll::Ready(Some(value)),
+                Ok(RunState::Halted) => return this.finish(None),
+                Ok(RunState::NeedsInput) => match Pin::new(&mut this.input).poll_next(cx) {
+                    Poll::Ready(Some(value)) => machine.push_input(value),
+                    Poll::Ready(None) => {
+                        let error = machine.error(|ip, instruction| IntcodeError::InputExhausted {
+                            ip,
+                            instruction,
+                        });
+                        return this.finish(Some(error));
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+                Ok(RunState::Stopped(limit)) => {
+                    let error = machine.error(|ip, instruction| IntcodeError::LimitReached {
+                        ip,
+                        instruction,
+                        limit,
+                    });
+                    return this.finish(Some(error));
+                }
+                Err(error) => return this.finish(Some(error)),
+            }
+        }
+    }
+}
+
+impl<S, M> FusedStream for MachineStream<S, M>
+where
+    S: Stream<Item = i64> + Unpin,
+    M: Memory,
+{
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
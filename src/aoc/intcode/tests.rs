@@ -0,0 +1,1397 @@
+use super::ascii::AsciiMachine;
+use super::assembler::assemble;
+use super::batch::{Batch, QueryResult};
+use super::cfg::analyze;
+use super::decompiler::decompile;
+use super::diff::{Range, Region};
+use super::extension::{Action, Exit, Extension, ExtensionError, Extensions};
+use super::loader::{parse, LoadError};
+use super::memory::{DenseMemory, FlatMemory, Memory, PagedMemory};
+use super::network::{Flow, Mode, Nat, Network, Packet, Router};
+use super::numeric::Number;
+use super::pipeline::Pipeline;
+use super::profiler;
+use super::stream::MachineStream;
+use super::{IntcodeError, Limit, Machine, RunState};
+use futures::executor::block_on;
+use futures::stream::{self, Stream, StreamExt};
+use num::BigInt;
+use std::collections::VecDeque;
+
+// Random programs run on `Machine` and on a deliberately naive evaluator, and
+// must end in the same state. Programs are built from valid instructions, but
+// jumps and writes land anywhere, so code gets overwritten, data gets executed
+// and every error path comes up sooner or later.
+
+const PROGRAMS: u64 = 400;
+const BUDGET: u64 = 300;
+
+// xorshift64*, so every failure can be replayed from its seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + self.below((high - low) as u64) as i64
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len() as u64) as usize]
+    }
+}
+
+const OPCODES: [i64; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+
+fn param_count(opcode: i64) -> usize {
+    match opcode {
+        1 | 2 | 7 | 8 => 3,
+        5 | 6 => 2,
+        3 | 4 | 9 => 1,
+        _ => 0,
+    }
+}
+
+fn writes(opcode: i64, param: usize) -> bool {
+    match opcode {
+        1 | 2 | 7 | 8 => param == 3,
+        3 => param == 1,
+        _ => false,
+    }
+}
+
+fn random_program(rng: &mut Rng) -> Vec<i64> {
+    let count = 1 + rng.below(40) as usize;
+    let opcodes: Vec<i64> = (0..count).map(|_| rng.pick(&OPCODES)).collect();
+    let starts: Vec<usize> = opcodes
+        .iter()
+        .scan(0, |address, opcode| {
+            let start = *address;
+            *address += 1 + param_count(*opcode);
+            Some(start)
+        })
+        .collect();
+    let code_len = starts.last().unwrap() + 1 + param_count(*opcodes.last().unwrap());
+    let data_len = rng.below(30) as usize;
+    let len = (code_len + data_len) as i64;
+
+    let mut program = Vec::new();
+
+    for opcode in opcodes {
+        let params = param_count(opcode);
+        let modes: Vec<i64> = (1..=params)
+            .map(|n| {
+                if writes(opcode, n) {
+                    rng.pick(&[0, 2])
+                } else {
+                    rng.pick(&[0, 1, 2])
+                }
+            })
+            .collect();
+        let word = modes.iter().enumerate().fold(opcode, |word, (n, mode)| {
+            word + mode * 10i64.pow(n as u32 + 2)
+        });
+
+        program.push(word);
+
+        for (n, mode) in modes.iter().enumerate() {
+            let jump_target = (opcode == 5 || opcode == 6) && n == 1;
+
+            program.push(match mode {
+                1 if jump_target => *starts
+                    .get(rng.below(starts.len() as u64 + 1) as usize)
+                    .unwrap_or(&0) as i64,
+                1 if opcode == 9 => rng.range(-10, 10),
+                1 => rng.range(-3, 20),
+                2 => rng.range(-5, len + 10),
+                _ => match rng.below(1000) {
+                    0 => 3_000_000,
+                    1..=99 => rng.range(len, len + 600),
+                    _ => rng.range(0, len),
+                },
+            });
+        }
+    }
+
+    program.extend((0..data_len).map(|_| rng.range(-3, 20)));
+    program
+}
+
+fn random_inputs(rng: &mut Rng) -> Vec<i64> {
+    (0..rng.below(8)).map(|_| rng.range(-5, 50)).collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Outcome {
+    result: Result<RunState, IntcodeError>,
+    ip: usize,
+    relative_base: i64,
+    halted: bool,
+    inputs: Vec<i64>,
+    outputs: Vec<i64>,
+    program: Vec<i64>,
+    extended: Vec<(usize, i64)>,
+}
+
+fn outcome<M: Memory>(machine: &Machine<M>, result: Result<RunState, IntcodeError>) -> Outcome {
+    let extended = machine.memory.extended();
+    assert_eq!(extended.len(), machine.memory.extended_len());
+
+    Outcome {
+        result,
+        ip: machine.ip,
+        relative_base: machine.relative_base,
+        halted: machine.halted,
+        inputs: machine.inputs.iter().cloned().collect(),
+        outputs: machine.values.iter().cloned().collect(),
+        program: machine.memory.to_vec(),
+        extended,
+    }
+}
+
+// Written straight from the puzzle text, sharing nothing with `Machine`.
+struct Reference {
+    len: usize,
+    memory: Vec<i64>,
+    ip: usize,
+    relative_base: i64,
+    halted: bool,
+    inputs: VecDeque<i64>,
+    outputs: Vec<i64>,
+    executed: u64,
+}
+
+impl Reference {
+    fn new(program: &[i64], inputs: &[i64]) -> Self {
+        Self {
+            len: program.len(),
+            memory: program.to_vec(),
+            ip: 0,
+            relative_base: 0,
+            halted: false,
+            inputs: inputs.iter().cloned().collect(),
+            outputs: Vec::new(),
+            executed: 0,
+        }
+    }
+
+    fn get(&self, address: usize) -> i64 {
+        self.memory.get(address).cloned().unwrap_or(0)
+    }
+
+    fn set(&mut self, address: usize, value: i64) {
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, 0);
+        }
+
+        self.memory[address] = value;
+    }
+
+    fn check(&self, address: i64) -> Result<usize, IntcodeError> {
+        if address < 0 {
+            Err(IntcodeError::NegativeAddress {
+                ip: self.ip,
+                instruction: self.get(self.ip),
+                address,
+            })
+        } else {
+            Ok(address as usize)
+        }
+    }
+
+    fn address(&self, n: usize) -> Result<usize, IntcodeError> {
+        let word = self.get(self.ip);
+        let mode = word / 10i64.pow(n as u32 + 1) % 10;
+        let raw = self.get(self.ip + n);
+
+        match mode {
+            0 => self.check(raw),
+            1 => Ok(self.ip + n),
+            2 => self.check(self.overflow(self.relative_base.checked_add(raw))?),
+            _ => Err(IntcodeError::InvalidMode {
+                ip: self.ip,
+                instruction: word,
+                mode,
+            }),
+        }
+    }
+
+    fn overflow(&self, value: Option<i64>) -> Result<i64, IntcodeError> {
+        value.ok_or(IntcodeError::Overflow {
+            ip: self.ip,
+            instruction: self.get(self.ip),
+        })
+    }
+
+    fn param(&self, n: usize) -> Result<i64, IntcodeError> {
+        Ok(self.get(self.address(n)?))
+    }
+
+    fn store(&mut self, n: usize, value: i64) -> Result<(), IntcodeError> {
+        let word = self.get(self.ip);
+
+        if word / 10i64.pow(n as u32 + 1) % 10 == 1 {
+            return Err(IntcodeError::ImmediateWrite {
+                ip: self.ip,
+                instruction: word,
+            });
+        }
+
+        let address = self.address(n)?;
+        self.set(address, value);
+        Ok(())
+    }
+
+    fn step(&mut self) -> Result<Option<RunState>, IntcodeError> {
+        let word = self.get(self.ip);
+
+        match word % 100 {
+            1 => {
+                let sum = self.param(1)?.checked_add(self.param(2)?);
+                self.store(3, self.overflow(sum)?)?;
+                self.ip += 4;
+            }
+            2 => {
+                let product = self.param(1)?.checked_mul(self.param(2)?);
+                self.store(3, self.overflow(product)?)?;
+                self.ip += 4;
+            }
+            3 => match self.inputs.front().cloned() {
+                Some(input) => {
+                    self.store(1, input)?;
+                    self.inputs.pop_front();
+                    self.ip += 2;
+                }
+                None => return Ok(Some(RunState::NeedsInput)),
+            },
+            4 => {
+                let value = self.param(1)?;
+                self.outputs.push(value);
+                self.ip += 2;
+            }
+            5 | 6 => {
+                let test = self.param(1)?;
+                let target = self.param(2)?;
+
+                if (test != 0) == (word % 100 == 5) {
+                    self.ip = self.check(target)?;
+                } else {
+                    self.ip += 3;
+                }
+            }
+            7 => {
+                let less = self.param(1)? < self.param(2)?;
+                self.store(3, i64::from(less))?;
+                self.ip += 4;
+            }
+            8 => {
+                let equal = self.param(1)? == self.param(2)?;
+                self.store(3, i64::from(equal))?;
+                self.ip += 4;
+            }
+            9 => {
+                let base = self.relative_base.checked_add(self.param(1)?);
+                self.relative_base = self.overflow(base)?;
+                self.ip += 2;
+            }
+            99 => {
+                self.halted = true;
+                return Ok(Some(RunState::Halted));
+            }
+            _ => {
+                return Err(IntcodeError::InvalidOpcode {
+                    ip: self.ip,
+                    instruction: word,
+                })
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn run(&mut self, budget: u64) -> Result<RunState, IntcodeError> {
+        loop {
+            if self.executed == budget {
+                return Ok(RunState::Stopped(Limit::Instructions));
+            }
+
+            match self.step()? {
+                Some(RunState::NeedsInput) => return Ok(RunState::NeedsInput),
+                state => {
+                    self.executed += 1;
+
+                    if let Some(state) = state {
+                        return Ok(state);
+                    }
+                }
+            }
+        }
+    }
+
+    fn outcome(&self, result: Result<RunState, IntcodeError>) -> Outcome {
+        let program_len = self.len.min(self.memory.len());
+
+        Outcome {
+            result,
+            ip: self.ip,
+            relative_base: self.relative_base,
+            halted: self.halted,
+            inputs: self.inputs.iter().cloned().collect(),
+            outputs: self.outputs.clone(),
+            program: self.memory[..program_len].to_vec(),
+            extended: (self.len..self.memory.len())
+                .map(|a| (a, self.memory[a]))
+                .filter(|(_, v)| *v != 0)
+                .collect(),
+        }
+    }
+}
+
+fn expected(program: &[i64], inputs: &[i64], budget: u64) -> Outcome {
+    let mut reference = Reference::new(program, inputs);
+    let result = reference.run(budget);
+    reference.outcome(result)
+}
+
+fn machine<M: Memory>(program: &[i64], inputs: &[i64], budget: u64) -> Machine<M> {
+    let mut machine = Machine::init(program).with_backend::<M>();
+    machine.inputs.extend(inputs);
+    machine.set_instruction_budget(Some(budget));
+    machine
+}
+
+fn run<M: Memory>(machine: &mut Machine<M>) -> Outcome {
+    let result = machine.run(vec![]);
+    outcome(machine, result)
+}
+
+fn matches_reference<M: Memory>(program: &[i64], inputs: &[i64], seed: u64) {
+    let expected = expected(program, inputs, BUDGET);
+
+    for cached in [true, false].iter() {
+        let mut machine = machine::<M>(program, inputs, BUDGET);
+        machine.set_decode_cache(*cached);
+
+        assert_eq!(
+            expected,
+            run(&mut machine),
+            "seed {} cache {} program {:?} inputs {:?}",
+            seed,
+            cached,
+            program,
+            inputs
+        );
+    }
+}
+
+#[test]
+fn random_programs_match_reference() {
+    for seed in 0..PROGRAMS {
+        let mut rng = Rng::new(seed);
+        let program = random_program(&mut rng);
+        let inputs = random_inputs(&mut rng);
+
+        matches_reference::<PagedMemory>(&program, &inputs, seed);
+        matches_reference::<FlatMemory>(&program, &inputs, seed);
+        matches_reference::<DenseMemory>(&program, &inputs, seed);
+    }
+}
+
+// One instruction per opcode and mode combination, with a relative base that
+// keeps relative parameters in range, then a halt.
+#[test]
+fn every_opcode_and_mode_matches_reference() {
+    let data = [7, -2, 0, 5, 11, 3, 0, 1, 9, 4];
+
+    for opcode in OPCODES.iter() {
+        let params = param_count(*opcode);
+
+        for modes in 0..3usize.pow(params as u32) {
+            let modes: Vec<i64> = (0..params)
+                .map(|n| (modes / 3usize.pow(n as u32) % 3) as i64)
+                .collect();
+            let word = modes.iter().enumerate().fold(*opcode, |word, (n, mode)| {
+                word + mode * 10i64.pow(n as u32 + 2)
+            });
+            let code_len = 2 + 1 + params + 1;
+            let mut program = vec![109, code_len as i64 + 2, word];
+
+            program.extend((0..params).map(|n| (code_len + n) as i64));
+            program.push(99);
+            program.extend(data.iter());
+
+            matches_reference::<PagedMemory>(&program, &[42], *opcode as u64);
+        }
+    }
+}
+
+#[test]
+fn immediate_writes_and_bad_modes_are_errors() {
+    let cases: [(&[i64], IntcodeError); 4] = [
+        (
+            &[11101, 1, 1, 0, 99],
+            IntcodeError::ImmediateWrite {
+                ip: 0,
+                instruction: 11101,
+            },
+        ),
+        (
+            &[301, 0, 0, 0, 99],
+            IntcodeError::InvalidMode {
+                ip: 0,
+                instruction: 301,
+                mode: 3,
+            },
+        ),
+        (
+            &[1, -1, 0, 0, 99],
+            IntcodeError::NegativeAddress {
+                ip: 0,
+                instruction: 1,
+                address: -1,
+            },
+        ),
+        (
+            &[1101, 1, -1, 4, 99],
+            IntcodeError::InvalidOpcode {
+                ip: 4,
+                instruction: 0,
+            },
+        ),
+    ];
+
+    for (program, error) in cases.iter() {
+        let mut machine = Machine::init(program);
+        let reference = expected(program, &[], BUDGET);
+
+        assert_eq!(Err(*error), machine.run(vec![]), "{:?}", program);
+        assert_eq!(Err(*error), reference.result, "{:?}", program);
+    }
+}
+
+// Sums, products, the relative base and relative addresses are all checked.
+#[test]
+fn overflow_is_an_error() {
+    let cases: [(&[i64], IntcodeError); 4] = [
+        (
+            &[109, i64::MAX, 109, 1, 99],
+            IntcodeError::Overflow {
+                ip: 2,
+                instruction: 109,
+            },
+        ),
+        (
+            &[109, i64::MAX, 1201, 1, 0, 0, 99],
+            IntcodeError::Overflow {
+                ip: 2,
+                instruction: 1201,
+            },
+        ),
+        (
+            &[1101, i64::MAX, 1, 0, 99],
+            IntcodeError::Overflow {
+                ip: 0,
+                instruction: 1101,
+            },
+        ),
+        (
+            &[1102, 1 << 62, 4, 0, 99],
+            IntcodeError::Overflow {
+                ip: 0,
+                instruction: 1102,
+            },
+        ),
+    ];
+
+    for (program, error) in cases.iter() {
+        let mut machine = Machine::init(program);
+        let reference = expected(program, &[], BUDGET);
+
+        assert_eq!(Err(*error), machine.run(vec![]), "{:?}", program);
+        assert_eq!(Err(*error), reference.result, "{:?}", program);
+    }
+}
+
+fn far_writes<M: Memory>() {
+    let far = 1 << 40;
+    let program = [1101, 7, 0, far, 4, far, 1101, 0, 0, far, 4, far, 99];
+    let mut machine = Machine::init(&program).with_backend::<M>();
+
+    assert_eq!(Ok(RunState::Halted), machine.run(vec![]));
+    assert_eq!(
+        vec![7, 0],
+        machine.values.iter().cloned().collect::<Vec<_>>()
+    );
+    assert_eq!(Vec::<(usize, i64)>::new(), machine.memory.extended());
+    assert_eq!(0, machine.memory.extended_len());
+    assert!(machine.memory.owned_bytes() < 1 << 20);
+}
+
+// A huge address is stored on its own instead of allocating everything below it.
+#[test]
+fn far_addresses_stay_small() {
+    far_writes::<PagedMemory>();
+    far_writes::<FlatMemory>();
+    far_writes::<DenseMemory>();
+}
+
+// Stopping at a random point, cloning, and running both on must give the same
+// result as one uninterrupted run, and writes to a clone stay in the clone.
+#[test]
+fn clones_run_like_the_original() {
+    for seed in 0..PROGRAMS {
+        let mut rng = Rng::new(seed);
+        let program = random_program(&mut rng);
+        let inputs = random_inputs(&mut rng);
+        let first = rng.below(BUDGET);
+        let expected = expected(&program, &inputs, BUDGET);
+
+        let mut original = machine::<PagedMemory>(&program, &inputs, first);
+
+        if original.run(vec![]) != Ok(RunState::Stopped(Limit::Instructions)) {
+            continue;
+        }
+
+        let first_cell = original.read(0);
+        let mut clone = original.clone();
+        clone.write(0, clone.read(0) + 1);
+        clone.write(2_000, 1);
+        clone.write(0, clone.read(0) - 1);
+        clone.write(2_000, 0);
+
+        assert_eq!(first_cell, original.read(0), "seed {}", seed);
+        assert_eq!(0, original.read(2_000), "seed {}", seed);
+
+        let remaining = BUDGET - first;
+
+        for mut machine in [original, clone] {
+            machine.set_instruction_budget(Some(remaining));
+            assert_eq!(expected, run(&mut machine), "seed {}", seed);
+        }
+    }
+}
+
+fn same_state<M: Memory, N: Memory>(a: &Machine<M>, b: &Machine<N>) -> bool {
+    a.ip == b.ip
+        && a.relative_base == b.relative_base
+        && a.halted == b.halted
+        && a.exit_code == b.exit_code
+        && a.inputs == b.inputs
+        && a.values == b.values
+        && a.memory.to_vec() == b.memory.to_vec()
+        && a.memory.extended() == b.memory.extended()
+}
+
+// Loading decodes the program once, and nothing derived from it decodes again.
+#[test]
+fn decode_cache_is_built_once() {
+    let machine = Machine::init(&[1101, 2, 3, 0, 99]);
+    let backend = machine.with_backend::<PagedMemory>();
+    let snapshot = <Machine>::from_text(&machine.to_text()).unwrap();
+    let cache = machine.clone().decode_cache.unwrap();
+
+    assert!(cache.shares(&backend.decode_cache.unwrap()));
+    assert!(snapshot.decode_cache.is_some());
+}
+
+#[test]
+fn snapshots_round_trip() {
+    for seed in 0..PROGRAMS {
+        let mut rng = Rng::new(seed);
+        let program = random_program(&mut rng);
+        let inputs = random_inputs(&mut rng);
+        let first = rng.below(BUDGET + 1);
+        let mut machine = machine::<DenseMemory>(&program, &inputs, first);
+
+        if machine.run(vec![]).is_err() {
+            continue;
+        }
+
+        let text = <Machine>::from_text(&machine.to_text()).unwrap();
+        let binary = <Machine>::from_bytes(&machine.to_bytes()).unwrap();
+
+        assert!(same_state(&machine, &text), "seed {} text", seed);
+        assert!(same_state(&machine, &binary), "seed {} binary", seed);
+
+        let mut runs = [machine, text, binary];
+
+        for machine in runs.iter_mut() {
+            machine.set_instruction_budget(Some(BUDGET - first));
+        }
+
+        let outcomes: Vec<Outcome> = runs.iter_mut().map(run).collect();
+
+        assert_eq!(outcomes[0], outcomes[1], "seed {} text", seed);
+        assert_eq!(outcomes[0], outcomes[2], "seed {} binary", seed);
+    }
+}
+
+// Version 1 had no exit code, and its files load without one.
+#[test]
+fn version_1_snapshots_load() {
+    let mut machine = Machine::init(&[3, 0, 99]);
+    machine.run(vec![]).unwrap();
+
+    let text = machine.to_text().replacen(" 2\n", " 1\n", 1);
+    let text = text.replacen("exit_code \n", "", 1);
+
+    // Magic, version, ip, relative base and halted come before the exit code.
+    let mut bytes = machine.to_bytes();
+    bytes[4] = 1;
+    bytes.remove(4 + 4 + 8 + 8 + 1);
+
+    assert!(same_state(&machine, &<Machine>::from_text(&text).unwrap()));
+    assert!(same_state(
+        &machine,
+        &<Machine>::from_bytes(&bytes).unwrap()
+    ));
+}
+
+// Rewinding a journaled run lands on the state of a fresh run that stopped there.
+#[test]
+fn journal_rewinds_to_earlier_states() {
+    for seed in 0..PROGRAMS {
+        let mut rng = Rng::new(seed);
+        let program = random_program(&mut rng);
+        let inputs = random_inputs(&mut rng);
+        let mut machine = machine::<PagedMemory>(&program, &inputs, BUDGET);
+        machine.start_journal();
+        let _ = machine.run(vec![]);
+
+        let count = machine.instruction_count().unwrap();
+        let target = rng.below(count as u64 + 1);
+        let branch = machine.branch(target as usize).unwrap();
+
+        let mut fresh = self::machine::<PagedMemory>(&program, &inputs, target);
+        let _ = fresh.run(vec![]);
+
+        // Outputs stay where the caller can see them, so compare everything else.
+        let mut rewound = machine.clone();
+        assert!(rewound.rewind_to(target as usize));
+        rewound.values = fresh.values.clone();
+
+        assert!(same_state(&fresh, &rewound), "seed {} at {}", seed, target);
+        assert_eq!(
+            rewound.memory.to_vec(),
+            branch.memory.to_vec(),
+            "seed {}",
+            seed
+        );
+        assert!(!machine.rewind_to(count + 1), "seed {}", seed);
+    }
+}
+
+struct Max;
+
+impl Extension for Max {
+    fn opcode(&self) -> i64 {
+        30
+    }
+
+    fn mnemonic(&self) -> &str {
+        "MAX"
+    }
+
+    fn params(&self) -> usize {
+        3
+    }
+
+    fn writes(&self) -> &[usize] {
+        &[3]
+    }
+
+    fn execute(&self, args: &mut [i64]) -> Action {
+        args[2] = args[0].max(args[1]);
+        Action::Continue
+    }
+}
+
+#[test]
+fn extensions_use_the_shared_modes() {
+    let mut extensions = Extensions::new();
+    extensions.register(Max).unwrap();
+    extensions.register(Exit(31)).unwrap();
+
+    // MAX #5, [20], [rb+1] then output [11] and exit with [11].
+    let mut program = vec![109, 10, 20130, 5, 20, 1, 4, 11, 1031, 11];
+    program.resize(21, 0);
+    program[20] = 9;
+
+    let mut machine = Machine::init(&program);
+    machine.set_extensions(Some(extensions.clone()));
+
+    assert_eq!(Ok(RunState::Halted), machine.run(vec![]));
+    assert_eq!(
+        vec![9],
+        machine.values.iter().cloned().collect::<Vec<i64>>()
+    );
+    assert_eq!(Some(9), machine.exit_code());
+
+    let text = <Machine>::from_text(&machine.to_text()).unwrap();
+    let binary = <Machine>::from_bytes(&machine.to_bytes()).unwrap();
+    assert_eq!((Some(9), Some(9)), (text.exit_code(), binary.exit_code()));
+
+    // Immediate writes and unclaimed opcodes are still errors.
+    for (word, error) in [(10130, "ImmediateWrite"), (32, "InvalidOpcode")].iter() {
+        let mut machine = Machine::init(&[*word, 1, 2, 3, 99]);
+        machine.set_extensions(Some(extensions.clone()));
+        let result = format!("{:?}", machine.run(vec![]));
+
+        assert!(result.contains(error), "{} {}", word, result);
+        assert_eq!(0, machine.ip);
+    }
+
+    assert_eq!(Err(ExtensionError::Duplicate(30)), extensions.register(Max));
+    assert_eq!(
+        Err(ExtensionError::Reserved(1)),
+        extensions.register(Exit(1))
+    );
+    assert_eq!(
+        Err(ExtensionError::Reserved(99)),
+        extensions.register(Exit(99))
+    );
+}
+
+// Writes both of its parameters, or takes as many as it's told.
+struct Swap(usize);
+
+impl Extension for Swap {
+    fn opcode(&self) -> i64 {
+        40
+    }
+
+    fn mnemonic(&self) -> &str {
+        "SWP"
+    }
+
+    fn params(&self) -> usize {
+        self.0
+    }
+
+    fn writes(&self) -> &[usize] {
+        &[1, 2]
+    }
+
+    fn execute(&self, args: &mut [i64]) -> Action {
+        args.swap(0, 1);
+        Action::Continue
+    }
+}
+
+#[test]
+fn journal_undoes_extensions() {
+    let mut extensions = Extensions::new();
+    extensions.register(Swap(2)).unwrap();
+    extensions.register(Exit(31)).unwrap();
+
+    // Swap [6] and [7], then exit with [6].
+    let program = [40, 6, 7, 31, 6, 0, 1, 2];
+    let mut machine = Machine::init(&program);
+    machine.set_extensions(Some(extensions));
+    machine.start_journal();
+
+    assert_eq!(Ok(RunState::Halted), machine.run(vec![]));
+    assert_eq!(
+        (2, 1, Some(2)),
+        (machine.read(6), machine.read(7), machine.exit_code())
+    );
+
+    assert!(machine.rewind_to(0));
+    assert_eq!(program.to_vec(), machine.memory.to_vec());
+    assert_eq!(None, machine.exit_code());
+    assert!(!machine.halted);
+
+    let error = ExtensionError::TooManyParams {
+        opcode: 40,
+        params: 18,
+    };
+    assert_eq!(Err(error), Extensions::new().register(Swap(18)));
+}
+
+// Doubles its first parameter on a machine of its own.
+struct Double;
+
+impl Extension for Double {
+    fn opcode(&self) -> i64 {
+        50
+    }
+
+    fn mnemonic(&self) -> &str {
+        "DBL"
+    }
+
+    fn params(&self) -> usize {
+        2
+    }
+
+    fn writes(&self) -> &[usize] {
+        &[2]
+    }
+
+    fn execute(&self, args: &mut [i64]) -> Action {
+        let mut machine = Machine::init(&[3, 9, 1002, 9, 2, 9, 4, 9, 99, 0]);
+        machine.run(vec![args[0]]).unwrap();
+        args[1] = machine.values[0];
+        Action::Continue
+    }
+}
+
+// A machine run while another is running records into the same profile.
+#[test]
+fn profiles_take_nested_runs() {
+    let mut extensions = Extensions::new();
+    extensions.register(Double).unwrap();
+
+    let mut machine = Machine::init(&[150, 21, 6, 4, 6, 99, 0]);
+    machine.set_extensions(Some(extensions));
+
+    profiler::start();
+    let result = machine.run(vec![]);
+    let profile = profiler::finish().unwrap();
+
+    assert_eq!(Ok(RunState::Halted), result);
+    assert_eq!(Some(&42), machine.values.front());
+    assert_eq!(7, profile.instructions);
+}
+
+#[test]
+fn numeric_machines_report_overflow() {
+    // [11] = 2^40 * 2^40, then [11] = [11] * [11], then output [11].
+    let big = 1i64 << 40;
+    let program = [1102, big, big, 11, 2, 11, 11, 11, 4, 11, 99, 0];
+    let machine = Machine::init(&program);
+
+    let mut narrow = machine.with_numbers::<i64>();
+    let error = IntcodeError::Overflow {
+        ip: 0,
+        instruction: 1102,
+    };
+    assert_eq!(Err(error), narrow.run(vec![]));
+
+    let mut wide = machine.with_numbers::<i128>();
+    let error = IntcodeError::Overflow {
+        ip: 4,
+        instruction: 2,
+    };
+    assert_eq!(Err(error), wide.run(vec![]));
+
+    let mut bignum = machine.with_numbers::<BigInt>();
+    assert_eq!(Ok(RunState::Halted), bignum.run(vec![]));
+    assert_eq!(Some(&num::pow(BigInt::from(big), 4)), bignum.values.front());
+}
+
+#[test]
+fn numeric_machines_match_reference() {
+    for seed in 0..PROGRAMS {
+        let mut rng = Rng::new(seed);
+        let program = random_program(&mut rng);
+        let inputs = random_inputs(&mut rng);
+        let mut reference = Reference::new(&program, &inputs);
+
+        // No budget here, so only programs that finish quickly are compared.
+        let expected = match reference.run(BUDGET) {
+            Ok(RunState::Stopped(_)) => continue,
+            result => reference.outcome(result),
+        };
+        let expected = (expected.result, expected.outputs, expected.ip);
+
+        assert_eq!(
+            expected,
+            run_numeric::<i64>(&program, &inputs),
+            "seed {}",
+            seed
+        );
+
+        if !matches!(expected.0, Err(IntcodeError::Overflow { .. })) {
+            let bignum = run_numeric::<BigInt>(&program, &inputs);
+            assert_eq!(expected, bignum, "seed {}", seed);
+        }
+    }
+}
+
+// BOOST's self test checks big number support, so every number type must pass it.
+#[test]
+fn numeric_machines_pass_boost() {
+    let boost = Machine::from_file("input/day9.txt").unwrap();
+
+    assert_eq!(vec![3_906_448_201], boost_keycode::<i64>(&boost));
+    assert_eq!(vec![3_906_448_201], boost_keycode::<i128>(&boost));
+    assert_eq!(vec![3_906_448_201], boost_keycode::<BigInt>(&boost));
+}
+
+fn boost_keycode<N: Number>(boost: &Machine) -> Vec<i64> {
+    let mut machine = boost.with_numbers::<N>();
+    machine.run_to_halt(vec![N::from(1)]).unwrap();
+    machine.values.iter().map(|v| v.to_i64().unwrap()).collect()
+}
+
+// The result, outputs and final ip.
+fn run_numeric<N: Number>(
+    program: &[i64],
+    inputs: &[i64],
+) -> (Result<RunState, IntcodeError>, Vec<i64>, usize) {
+    let mut machine = Machine::init(program).with_numbers::<N>();
+    machine.inputs.extend(inputs.iter().map(|v| N::from(*v)));
+    let result = machine.run(vec![]);
+    let outputs = machine.values.iter().map(|v| v.to_i64().unwrap()).collect();
+
+    (result, outputs, machine.ip)
+}
+
+#[test]
+fn batches_match_sequential_runs() {
+    let mut rng = Rng::new(7);
+    let program = random_program(&mut rng);
+    let queries: Vec<Vec<i64>> = (0..200).map(|_| random_inputs(&mut rng)).collect();
+
+    // Unlimited runs, so only programs that stop by themselves will do.
+    let stops = queries.iter().all(|inputs| {
+        let mut reference = Reference::new(&program, inputs);
+        !matches!(reference.run(BUDGET), Ok(RunState::Stopped(_)))
+    });
+    assert!(stops, "{:?}", program);
+
+    let machine = Machine::init(&program);
+    let expected: Vec<QueryResult> = queries
+        .iter()
+        .map(|inputs| {
+            let mut machine = machine.clone();
+            machine.run_to_halt(inputs.clone())?;
+            Ok(machine.values.iter().cloned().collect())
+        })
+        .collect();
+
+    for threads in [1, 4].iter() {
+        let mut batch = Batch::new(&machine);
+        batch.set_threads(*threads);
+        assert_eq!(expected, batch.run(&queries), "{} threads", threads);
+
+        // The second run is served from the cache.
+        batch.set_cache(true);
+        assert_eq!(expected, batch.run(&queries), "{} threads", threads);
+        assert_eq!(expected, batch.run(&queries), "{} threads", threads);
+    }
+}
+
+// What `run` would have returned, from why a stream ended.
+fn stream_result(error: Option<IntcodeError>) -> Result<RunState, IntcodeError> {
+    match error {
+        None => Ok(RunState::Halted),
+        Some(IntcodeError::InputExhausted { .. }) => Ok(RunState::NeedsInput),
+        Some(IntcodeError::LimitReached { limit, .. }) => Ok(RunState::Stopped(limit)),
+        Some(error) => Err(error),
+    }
+}
+
+#[test]
+fn streams_match_reference() {
+    for seed in 0..PROGRAMS {
+        let mut rng = Rng::new(seed);
+        let program = random_program(&mut rng);
+        let inputs = random_inputs(&mut rng);
+        let expected = expected(&program, &inputs, BUDGET);
+
+        let machine = machine::<PagedMemory>(&program, &[], BUDGET);
+        let mut outputs = MachineStream::new(machine, stream::iter(inputs.clone()));
+        let values: Vec<i64> = block_on((&mut outputs).collect());
+        let result = stream_result(outputs.error());
+
+        // Inputs are pulled one at a time, so the leftovers stay in the stream.
+        let actual = Outcome {
+            inputs: expected.inputs.clone(),
+            outputs: values,
+            ..outcome(&outputs.into_machine(), result)
+        };
+
+        assert_eq!(expected, actual, "seed {} program {:?}", seed, program);
+    }
+}
+
+// A prompt, the halt after it, and a run that hit its budget all look alike
+// from the text, so the state comes back with it.
+#[test]
+fn ascii_prompts_report_the_state() {
+    let program = [104, 104, 104, 105, 3, 0, 99];
+    let mut droid = AsciiMachine::new(Machine::init(&program));
+    let (output, state) = droid.read_until_prompt().unwrap();
+
+    assert_eq!(("hi", RunState::NeedsInput), (output.text.as_str(), state));
+
+    droid.send_line("x");
+    assert_eq!(RunState::Halted, droid.read_until_prompt().unwrap().1);
+
+    let mut droid = AsciiMachine::new(Machine::init(&program));
+    droid.machine.set_instruction_budget(Some(1));
+    let stopped = RunState::Stopped(Limit::Instructions);
+
+    assert_eq!(stopped, droid.read_until_prompt().unwrap().1);
+}
+
+// A token goes round a ring of three machines to the NAT at address 3, each
+// machine adding its address to x and copying the packet to address -1.
+const RING: &str = "
+            IN [addr]
+            JT [addr], #loop
+            OUT #1
+            OUT #100
+            OUT #7
+    loop:   IN [x]
+            EQ [x], #-1, [t]
+            JT [t], #loop
+            IN [y]
+            OUT #-1
+            OUT [x]
+            OUT [y]
+            ADD [addr], #1, [to]
+            ADD [x], [addr], [x]
+            OUT [to]
+            OUT [x]
+            OUT [y]
+            JT #1, #loop
+    addr:   DATA 0
+    x:      DATA 0
+    y:      DATA 0
+    t:      DATA 0
+    to:     DATA 0
+";
+
+// Everything routed outside the network, in order, on top of the NAT.
+struct Recorder {
+    nat: Nat,
+    routed: Vec<Packet>,
+    idles: usize,
+}
+
+impl Router for Recorder {
+    fn route(&mut self, packet: Packet) -> Flow {
+        self.routed.push(packet);
+        self.nat.route(packet)
+    }
+
+    fn idle(&mut self) -> Flow {
+        self.idles += 1;
+        self.nat.idle()
+    }
+}
+
+fn run_ring(mode: Mode) -> Recorder {
+    let program = Machine::init(&assemble(RING).unwrap());
+    let mut recorder = Recorder {
+        nat: Nat::new(3),
+        routed: Vec::new(),
+        idles: 0,
+    };
+
+    Network::boot(&program, 3).run(&mut recorder, mode).unwrap();
+    recorder
+}
+
+// Threads may interleave the copies differently, but not what gets routed.
+#[test]
+fn network_modes_route_the_same_packets() {
+    let mut threaded = run_ring(Mode::Threaded);
+    let mut deterministic = run_ring(Mode::Deterministic);
+
+    let key = |p: &Packet| (p.source, p.destination, p.x, p.y);
+    threaded.routed.sort_by_key(key);
+    deterministic.routed.sort_by_key(key);
+    assert_eq!(deterministic.routed, threaded.routed);
+
+    // The token comes back once from the NAT, which stops when y repeats.
+    let to_nat: Vec<i64> = deterministic
+        .routed
+        .iter()
+        .filter(|p| p.destination == 3)
+        .map(|p| p.x)
+        .collect();
+    assert_eq!(vec![103, 106], to_nat);
+    assert_eq!(7, deterministic.routed.len());
+    assert_eq!((2, 2), (deterministic.idles, threaded.idles));
+    assert_eq!(Some(7), deterministic.nat.repeated_y);
+    assert_eq!(Some(7), threaded.nat.repeated_y);
+}
+
+// Writes next to each other merge into one range, but never across the end of
+// the program. The run stops waiting for input, with nothing left to read.
+#[test]
+fn run_diff_groups_changes() {
+    let program = [
+        1101, 1, 1, 23, 1101, 2, 2, 24, 1101, 3, 3, 26, 1101, 4, 4, 27, 1101, 5, 5, 100, 3, 23, 99,
+        0, 0, 0, 0,
+    ];
+    let mut machine = <Machine>::init(&program);
+    let (state, diff) = machine.run_diff(vec![]).unwrap();
+
+    let range = |region, start, old: &[i64], new: &[i64]| Range {
+        region,
+        start,
+        old: old.to_vec(),
+        new: new.to_vec(),
+    };
+    let expected = vec![
+        range(Region::Program, 23, &[0, 0], &[2, 4]),
+        range(Region::Program, 26, &[0], &[6]),
+        range(Region::Extended, 27, &[0], &[8]),
+        range(Region::Extended, 100, &[0], &[10]),
+    ];
+
+    assert_eq!(RunState::NeedsInput, state);
+    assert_eq!(expected, diff.ranges);
+    assert_eq!(5, diff.len());
+}
+
+// Day 7's examples, as a chain and as a feedback loop.
+#[test]
+fn pipelines_chain_and_loop() {
+    let program = [
+        3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+    ];
+    let chain = Pipeline::chain(&Machine::init(&program), &[4, 3, 2, 1, 0]);
+    let run = chain.run(vec![0]).unwrap();
+
+    assert_eq!((Some(43210), true), (run.output, run.halted));
+    assert_eq!(vec![3, 4], run.traces[1].inputs);
+
+    let program = [
+        3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28,
+        1005, 28, 6, 99, 0, 0, 5,
+    ];
+    let feedback = Pipeline::feedback_loop(&Machine::init(&program), &[9, 8, 7, 6, 5]);
+    let run = feedback.run(vec![0]).unwrap();
+
+    assert_eq!((Some(139_629_729), true), (run.output, run.halted));
+}
+
+// A diamond whose two sides join in a sum, with the exit on one side.
+#[test]
+fn pipelines_take_any_topology() {
+    let double = Machine::init(&[3, 9, 1002, 9, 2, 9, 4, 9, 99, 0]);
+    let add_one = Machine::init(&[3, 9, 1001, 9, 1, 9, 4, 9, 99, 0]);
+    let sum = Machine::init(&[3, 11, 3, 12, 1, 11, 12, 11, 4, 11, 99, 0, 0]);
+
+    let mut pipeline = Pipeline::new();
+    let top = pipeline.add_stage(double.clone());
+    let left = pipeline.add_stage(add_one);
+    let right = pipeline.add_stage(double);
+    let bottom = pipeline.add_stage(sum);
+
+    pipeline.connect(top, left);
+    pipeline.connect(top, right);
+    pipeline.connect(left, bottom);
+    pipeline.connect(right, bottom);
+    pipeline.set_exit(left);
+
+    let run = pipeline.run(vec![5]).unwrap();
+
+    assert_eq!((Some(11), true), (run.output, run.halted));
+    assert_eq!(vec![11, 20], run.traces[bottom].inputs);
+    assert_eq!(vec![31], run.traces[bottom].outputs);
+}
+
+// Day 7's first example, with each amplifier reading the one before it.
+#[test]
+fn streams_chain() {
+    let program = [
+        3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+    ];
+    let mut signal: Box<dyn Stream<Item = i64> + Unpin> = Box::new(stream::iter(vec![0]));
+
+    for phase in [4, 3, 2, 1, 0].iter() {
+        let input = stream::iter(vec![*phase]).chain(signal);
+        signal = Box::new(MachineStream::new(<Machine>::init(&program), input));
+    }
+
+    assert_eq!(vec![43210], block_on(signal.collect::<Vec<i64>>()));
+}
+
+// Day 7's feedback loop example, closed with a channel.
+#[test]
+fn streams_loop_through_channels() {
+    let program = [
+        3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28,
+        1005, 28, 6, 99, 0, 0, 5,
+    ];
+    let (feedback, first) = MachineStream::channel(<Machine>::init(&program));
+    feedback.unbounded_send(9).unwrap();
+    feedback.unbounded_send(0).unwrap();
+
+    let mut signal: Box<dyn Stream<Item = i64> + Unpin> = Box::new(first);
+
+    for phase in [8, 7, 6, 5].iter() {
+        let input = stream::iter(vec![*phase]).chain(signal);
+        signal = Box::new(MachineStream::new(<Machine>::init(&program), input));
+    }
+
+    let last = block_on(async {
+        let mut last = None;
+
+        while let Some(value) = signal.next().await {
+            feedback.unbounded_send(value).unwrap();
+            last = Some(value);
+        }
+
+        last
+    });
+
+    assert_eq!(Some(139_629_729), last);
+}
+
+// A routine called the way the puzzle inputs call them, with a loop in it.
+#[test]
+fn decompiles_routines_and_loops() {
+    let source = "
+                ARB #100
+                IN [rb+1]
+                ADD #back, #0, [rb+0]
+                JT #1, #sum
+        back:   OUT [rb+1]
+                HLT
+
+        ; 1 + 2 + .. + n
+        sum:    ARB #4
+                ADD #0, #0, [rb-2]
+        loop:   EQ [rb-3], #0, [rb-1]
+                JT [rb-1], #done
+                ADD [rb-2], [rb-3], [rb-2]
+                ADD [rb-3], #-1, [rb-3]
+                JT #1, #loop
+        done:   ADD [rb-2], #0, [rb-3]
+                ARB #-4
+                JT #1, [rb+0]
+    ";
+    let program = assemble(source).unwrap();
+
+    let mut machine = <Machine>::init(&program);
+    machine.run_to_halt(vec![4]).unwrap();
+    assert_eq!(vec![10], Vec::from(machine.values));
+
+    let expected = "\
+fn main() {
+    s1 = f0014(input());
+    output(s1);
+    halt;
+}
+
+fn f0014(a1) {
+    var l1, l2;
+    l1 = 0;
+    while (a1 != 0) {
+        l1 = l1 + a1;
+        a1 = a1 - 1;
+    }
+    a1 = l1;
+    return a1;
+}
+";
+    assert_eq!(expected, decompile(&program));
+}
+
+// Operands and frames big enough to overflow any arithmetic done on them.
+#[test]
+fn analyses_survive_huge_operands() {
+    let programs: [&[i64]; 3] = [
+        &[109, -100, 2206, 8, 25, 20201, i64::MAX, 27, 0, 2105, -4, 40],
+        &[1102, 1 << 62, 4, 0, 1105, 1, 0, 99],
+        &[21101, 7, 0, 0, 1105, 1, 8, 99, 109, 1000, 204, i64::MAX, 99],
+    ];
+
+    for program in programs.iter() {
+        analyze(program);
+        assert!(decompile(program).starts_with("fn main() {\n"));
+    }
+}
+
+// Random code has none of the conventions, which must not trip anything up.
+#[test]
+fn decompiles_random_programs() {
+    for seed in 0..PROGRAMS {
+        let program = random_program(&mut Rng::new(seed));
+        assert!(
+            decompile(&program).starts_with("fn main() {\n"),
+            "seed {}",
+            seed
+        );
+    }
+}
+
+// Each input takes one branch. Together they cover the program, and the cells
+// only ever accessed show up as data.
+#[test]
+fn coverage_separates_code_from_data() {
+    let source = "
+                IN [value]
+                JT [value], #double
+                OUT [zero]
+                HLT
+        double: MUL [value], #2, [value]
+                OUT [value]
+                HLT
+        value:  DATA 0
+        zero:   DATA 0, 7
+    ";
+    let program = assemble(source).unwrap();
+    let coverage = |input| {
+        let mut machine = <Machine>::init(&program);
+        machine.start_coverage();
+        machine.run_to_halt(vec![input]).unwrap();
+        machine.stop_coverage().unwrap()
+    };
+
+    let mut both = coverage(0);
+    let doubled = coverage(3);
+    assert_eq!(
+        vec![0, 2, 8, 12, 14],
+        doubled.executed.keys().cloned().collect::<Vec<_>>()
+    );
+
+    let listing = "         1    0000: IN [15]
+         1    0002: JT [15], #8
+         -    0005: OUT [16]
+         -    0007: HLT
+         1    0008: MUL [15], #2, [15]
+         1    0012: OUT [15]
+         1    0014: HLT
+           RW 0015: DATA 0
+              0016: DATA 0, 7
+";
+    assert_eq!(listing, doubled.listing(&program));
+
+    both.merge(&doubled);
+    assert_eq!(
+        "7 of 7 instructions executed, 2 cells read, 1 written",
+        both.summary(&program)
+    );
+    assert!(both
+        .to_lcov("test", &program)
+        .ends_with("DA:14,1\nLF:7\nLH:7\nend_of_record\n"));
+}
+
+// Columns count characters, not bytes.
+#[test]
+fn loader_reports_columns() {
+    match parse("1, 2 # é\n3, é, 4") {
+        Err(LoadError::Syntax { line, column, .. }) => assert_eq!((2, 4), (line, column)),
+        result => panic!("{:?}", result),
+    }
+}
+
+#[test]
+fn assembler_rejects_label_overflow() {
+    assert_eq!(Ok(vec![1, 0]), assemble("x: DATA x+1, x"));
+
+    let error = assemble("DATA 0\nx: DATA x+9223372036854775807").unwrap_err();
+    assert_eq!(2, error.line);
+}
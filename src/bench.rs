@@ -0,0 +1,202 @@
+use super::aoc::intcode::memory::{DenseMemory, FlatMemory, Memory, PagedMemory};
+use super::aoc::intcode::network::{Mode, Nat, Network};
+use super::aoc::intcode::{Machine, RunState};
+use std::collections::{HashSet, VecDeque};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+// Timings for the intcode library on the puzzle inputs. The day workloads
+// drive the same programs the way the solutions do, but on any backend, and
+// leave checking the answers to the solutions.
+
+const CLONES: usize = 100_000;
+const READ_PASSES: usize = 2_000;
+const STACK_CELLS: usize = 1_000;
+const DAY_RUNS: usize = 20;
+
+fn measure<F>(f: F) -> Duration
+where
+    F: FnOnce(),
+{
+    let start = Instant::now();
+    f();
+    Instant::now().duration_since(start)
+}
+
+fn header() {
+    println!(
+        "{:<24}{:>14}{:>14}{:>14}",
+        "", "Vec+HashMap", "dense Vec", "COW pages"
+    );
+}
+
+fn row(name: &str, times: [Duration; 3]) {
+    println!(
+        "{:<24}{:>14?}{:>14?}{:>14?}",
+        name, times[0], times[1], times[2]
+    );
+}
+
+fn clone_and_write<M: Memory>(memory: &M) -> Duration {
+    let len = memory.program_len();
+
+    measure(|| {
+        for i in 0..CLONES {
+            let mut copy = memory.clone();
+            copy.write(i % len, 1);
+            black_box(copy);
+        }
+    })
+}
+
+fn read_range<M: Memory>(memory: &M, start: usize, end: usize) -> Duration {
+    measure(|| {
+        let mut sum = 0_i64;
+        for _ in 0..READ_PASSES {
+            for a in start..end {
+                sum = sum.wrapping_add(memory.read(black_box(a)));
+            }
+        }
+        black_box(sum);
+    })
+}
+
+fn workloads<M: Memory>(program: &[i64]) -> [Duration; 3] {
+    let mut memory = M::new(program);
+    let len = program.len();
+    let clones = clone_and_write(&memory);
+    let reads = read_range(&memory, 0, len);
+
+    for a in len..len + STACK_CELLS {
+        memory.write(a, a as i64);
+    }
+
+    [clones, reads, read_range(&memory, len, len + STACK_CELLS)]
+}
+
+// Compares the memory backends on clones, program reads and extended reads.
+pub fn memory(path: &str) {
+    let program = Machine::from_file(path).unwrap().memory.to_vec();
+    let flat = workloads::<FlatMemory>(&program);
+    let dense = workloads::<DenseMemory>(&program);
+    let paged = workloads::<PagedMemory>(&program);
+
+    println!("{} ({} cells)", path, program.len());
+    header();
+
+    for (i, name) in ["clone + write", "program reads", "extended reads"]
+        .iter()
+        .enumerate()
+    {
+        row(name, [flat[i], dense[i], paged[i]]);
+    }
+}
+
+fn day9<M: Memory>(machine: Machine<M>) {
+    for input in 1..=2 {
+        machine.clone().run_to_halt(vec![input]).unwrap();
+    }
+}
+
+// Floods the day 15 maze, each open cell found by a clone of the droid that
+// reached the cell next to it.
+fn day15<M: Memory>(machine: Machine<M>) {
+    let moves = [(1, (0, -1)), (2, (0, 1)), (3, (-1, 0)), (4, (1, 0))];
+    let mut seen: HashSet<(i32, i32)> = HashSet::new();
+    let mut droids = VecDeque::new();
+    droids.push_back(((0, 0), machine));
+    seen.insert((0, 0));
+
+    while let Some(((x, y), droid)) = droids.pop_front() {
+        for (direction, (dx, dy)) in moves.iter() {
+            let next = (x + dx, y + dy);
+
+            if !seen.insert(next) {
+                continue;
+            }
+
+            let mut droid = droid.clone();
+            droid.push_input(*direction);
+
+            if droid.run_until_output().unwrap() != RunState::Output(0) {
+                droids.push_back((next, droid));
+            }
+        }
+    }
+}
+
+// The 50x50 scan of the tractor beam, one clone per point.
+fn day19<M: Memory>(machine: Machine<M>) {
+    for y in 0..50 {
+        for x in 0..50 {
+            machine.clone().run_to_halt(vec![x, y]).unwrap();
+        }
+    }
+}
+
+// Threaded, like the solution.
+fn day23<M: Memory + Send>(machine: Machine<M>) {
+    Network::boot(&machine, 50)
+        .run(&mut Nat::new(255), Mode::Threaded)
+        .unwrap();
+}
+
+fn best_time<M: Memory>(path: &str, workload: fn(Machine<M>), decode_cache: bool) -> Duration {
+    let mut machine = Machine::from_file(path).unwrap().with_backend::<M>();
+    machine.set_decode_cache(decode_cache);
+
+    (0..DAY_RUNS)
+        .map(|_| {
+            let machine = machine.clone();
+            measure(|| workload(machine))
+        })
+        .min()
+        .unwrap()
+}
+
+fn day_times<M: Memory + Send>() -> [Duration; 3] {
+    [
+        best_time::<M>("input/day9.txt", day9, true),
+        best_time::<M>("input/day15.txt", day15, true),
+        best_time::<M>("input/day23.txt", day23, true),
+    ]
+}
+
+// Best of several runs of each day per backend.
+pub fn days() {
+    let flat = day_times::<FlatMemory>();
+    let dense = day_times::<DenseMemory>();
+    let paged = day_times::<PagedMemory>();
+
+    header();
+
+    for (i, name) in ["day 9", "day 15", "day 23"].iter().enumerate() {
+        row(name, [flat[i], dense[i], paged[i]]);
+    }
+}
+
+fn decode_times(path: &str, workload: fn(Machine)) -> (Duration, Duration) {
+    (
+        best_time(path, workload, false),
+        best_time(path, workload, true),
+    )
+}
+
+// The heaviest days with and without the decode cache, on the default memory.
+pub fn decode() {
+    let times = [
+        decode_times("input/day15.txt", day15),
+        decode_times("input/day19.txt", day19),
+        decode_times("input/day23.txt", day23),
+    ];
+
+    println!(
+        "{:<24}{:>14}{:>14}{:>9}",
+        "", "decoding", "cached", "speedup"
+    );
+
+    for (name, (plain, cached)) in ["day 15", "day 19", "day 23"].iter().zip(times.iter()) {
+        let speedup = plain.as_secs_f64() / cached.as_secs_f64();
+        println!("{:<24}{:>14?}{:>14?}{:>8.2}x", name, plain, cached, speedup);
+    }
+}
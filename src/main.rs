@@ -1,6 +1,14 @@
+use aoc::intcode::memory::Memory;
+use aoc::intcode::{
+    assembler, cfg, debugger, decompiler, diff, disassembler, extension, profiler, Machine,
+    RunState,
+};
+use std::env;
+use std::fs;
 use std::time::Instant;
 
 mod aoc;
+mod bench;
 
 fn time<F>(f: F)
 where
@@ -39,6 +47,197 @@ fn solve_all() {
     println!("Done");
 }
 
+const INTCODE_DAYS: [(usize, fn()); 11] = [
+    (2, aoc::day2::solve),
+    (5, aoc::day5::solve),
+    (7, aoc::day7::solve),
+    (9, aoc::day9::solve),
+    (11, aoc::day11::solve),
+    (13, aoc::day13::solve),
+    (15, aoc::day15::solve),
+    (17, aoc::day17::solve),
+    (19, aoc::day19::solve),
+    (21, aoc::day21::solve),
+    (23, aoc::day23::solve),
+];
+
+fn profile_all(json_path: Option<&str>) {
+    let mut reports = Vec::new();
+
+    for (day, solve) in INTCODE_DAYS.iter() {
+        profiler::start();
+        solve();
+        let profile = profiler::finish().unwrap();
+
+        println!("{}", profile.report());
+        reports.push(format!(
+            "{{\"day\":{},\"profile\":{}}}",
+            day,
+            profile.to_json()
+        ));
+    }
+
+    if let Some(path) = json_path {
+        save(path, &format!("[{}]\n", reports.join(",")));
+    }
+}
+
+// A path of "-" reads the program from stdin.
+fn load(path: &str) -> Option<Machine> {
+    let machine = if path == "-" {
+        Machine::from_stdin()
+    } else {
+        Machine::from_file(path)
+    };
+
+    match machine {
+        Ok(machine) => Some(machine),
+        Err(error) => {
+            println!("{}: {}", path, error);
+            None
+        }
+    }
+}
+
+// Loads a program for the extension dialect with the given inputs queued.
+// Reports a failed write the same way `load` reports a failed read.
+fn save(path: &str, contents: &str) {
+    if let Err(error) = fs::write(path, contents) {
+        println!("{}: {}", path, error);
+    }
+}
+
+fn load_with_inputs(path: &str, inputs: &[&str]) -> Option<Machine> {
+    let inputs: Vec<i64> = match inputs.iter().map(|i| i.parse()).collect() {
+        Ok(inputs) => inputs,
+        Err(error) => {
+            println!("Invalid input: {}", error);
+            return None;
+        }
+    };
+
+    let mut machine = load(path)?;
+    machine.inputs.extend(inputs);
+    machine.set_extensions(Some(extension::dialect()));
+    Some(machine)
+}
+
+// Runs with the extension dialect and prints what the program wrote.
+fn run(path: &str, inputs: &[&str]) {
+    let mut machine = match load_with_inputs(path, inputs) {
+        Some(machine) => machine,
+        None => return,
+    };
+
+    let state = machine.run(vec![]);
+
+    println!("{:?}", machine.values);
+
+    match state {
+        Ok(RunState::NeedsInput) => println!("Waiting for input"),
+        Ok(_) => {
+            if let Some(code) = machine.exit_code() {
+                println!("Exit code {}", code);
+            }
+        }
+        Err(error) => println!("Error: {}", error),
+    }
+}
+
+// Prints the program annotated with what the run touched.
+fn coverage(path: &str, lcov: Option<&str>, inputs: &[&str]) {
+    let mut machine = match load_with_inputs(path, inputs) {
+        Some(machine) => machine,
+        None => return,
+    };
+
+    let program = machine.memory.to_vec();
+    machine.start_coverage();
+    let state = machine.run(vec![]);
+    let coverage = machine.coverage().unwrap();
+
+    print!("{}", coverage.listing(&program));
+    println!("{}", coverage.summary(&program));
+
+    match state {
+        Ok(RunState::NeedsInput) => println!("Waiting for input"),
+        Ok(_) => {}
+        Err(error) => println!("Error: {}", error),
+    }
+
+    if let Some(output) = lcov {
+        save(output, &coverage.to_lcov(path, &program));
+    }
+}
+
+const USAGE: &str = "\
+Usage: advent_of_code_2019 [command]
+
+With no command every day is solved. A <program> of - is read from stdin.
+
+Commands:
+  disassemble <program>
+  assemble <source>
+  cfg <program> [--dot <output>]
+  decompile <program>
+  debug <program>
+  run <program> [input..]
+  coverage <program> [--lcov <output>] [input..]
+  diff <snapshot> <snapshot>
+  profile [--json <output>]
+  bench [--decode | <program>]";
+
 fn main() {
-    time(solve_all);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+
+    match args.as_slice() {
+        [] => time(solve_all),
+        ["disassemble", path] => {
+            if let Some(machine) = load(path) {
+                print!("{}", disassembler::listing(&machine.memory.to_vec()));
+            }
+        }
+        ["assemble", path] => match fs::read_to_string(path) {
+            Ok(source) => match assembler::assemble(&source) {
+                Ok(program) => println!("{}", assembler::to_program_text(&program)),
+                Err(error) => println!("{}: {}", path, error),
+            },
+            Err(error) => println!("{}: {}", path, error),
+        },
+        ["cfg", path] => {
+            if let Some(machine) = load(path) {
+                print!("{}", cfg::analyze(&machine.memory.to_vec()).summary());
+            }
+        }
+        ["cfg", path, "--dot", output] => {
+            if let Some(machine) = load(path) {
+                save(output, &cfg::analyze(&machine.memory.to_vec()).to_dot());
+            }
+        }
+        ["decompile", path] => {
+            if let Some(machine) = load(path) {
+                print!("{}", decompiler::decompile(&machine.memory.to_vec()));
+            }
+        }
+        ["debug", path] => {
+            if let Some(machine) = load(path) {
+                debugger::repl(machine);
+            }
+        }
+        ["diff", a, b] => match (<Machine>::load(a), <Machine>::load(b)) {
+            (Ok(before), Ok(after)) => print!("{}", diff::diff(&before, &after)),
+            (Err(error), _) => println!("{}: {}", a, error),
+            (_, Err(error)) => println!("{}: {}", b, error),
+        },
+        ["run", path, inputs @ ..] => run(path, inputs),
+        ["coverage", path, "--lcov", output, inputs @ ..] => coverage(path, Some(output), inputs),
+        ["coverage", path, inputs @ ..] => coverage(path, None, inputs),
+        ["profile"] => profile_all(None),
+        ["profile", "--json", path] => profile_all(Some(path)),
+        ["bench"] => bench::days(),
+        ["bench", "--decode"] => bench::decode(),
+        ["bench", path] => bench::memory(path),
+        _ => println!("{}", USAGE),
+    }
 }